@@ -0,0 +1,277 @@
+/*
+Build time validation of the shaders of src/shaders, so a broken shader fails `cargo build` instead
+of panicking once the window is open.
+
+Every shader of the library (shader_library.rs) goes through the same preprocessor as at runtime (preprocessor.rs), then is parsed and
+validated by naga's GLSL front end, which is pure Rust and needs no GPU. naga only knows the Vulkan
+flavor of GLSL 4.50, so the code is adapted first, without moving any line: the `#version` becomes
+450, and the uniforms get the binding naga insists on. naga has no geometry stage, so geometry
+shaders are only checked by the driver.
+
+The outputs of the vertex shader of every program of `shader_library::PROGRAMS` must also match the
+inputs of its fragment shader, by name and type, which the driver only checks when linking. And
+every file of src/shaders must be in the library, so none of them escapes the validation.
+ */
+
+// Only the parts without any GL call are needed here
+#[allow(dead_code)]
+#[path = "src/diagnostics.rs"]
+mod diagnostics;
+#[allow(dead_code)]
+#[path = "src/preprocessor.rs"]
+mod preprocessor;
+#[allow(dead_code)]
+#[path = "src/shader_library.rs"]
+mod shader_library;
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use naga::front::glsl::{Frontend, Options};
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+use naga::{Binding, Handle, Module, ShaderStage, Span, Type, TypeInner};
+use diagnostics::{Diagnostic, Severity};
+use preprocessor::{preprocess, Defines, LineDirectives};
+use shader_library::{LibraryShader, INCLUDE_FILES, PROGRAMS, SHADERS};
+
+const SHADER_DIRECTORY: &str = "src/shaders";
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", SHADER_DIRECTORY);
+    println!("cargo:rerun-if-changed=src/preprocessor.rs");
+    println!("cargo:rerun-if-changed=src/diagnostics.rs");
+    println!("cargo:rerun-if-changed=src/shader_library.rs");
+
+    let mut errors = Vec::new();
+    for path in shader_paths(Path::new(SHADER_DIRECTORY), "") {
+        let registered = SHADERS.iter().any(|library_shader| library_shader.file.path == path)
+            || INCLUDE_FILES.iter().any(|file| file.path == path);
+        if !registered {
+            errors.push(format!("error: {}/{} is neither a shader of shader_library::SHADERS, nor one of \
+                                 shader_library::INCLUDE_FILES", SHADER_DIRECTORY, path));
+        }
+    }
+
+    for library_shader in SHADERS {
+        let Some(stage) = naga_stage(library_shader) else {
+            continue;
+        };
+        if let Err(error) = parse(library_shader, stage) {
+            errors.push(error);
+        }
+    }
+
+    for program in PROGRAMS {
+        let shaders: Vec<&LibraryShader> = program.iter().filter_map(|name| shader_library::shader(name)).collect();
+        // The geometry stage in between can't be parsed, so there is nothing to compare
+        let [vertex, fragment] = shaders[..] else {
+            continue;
+        };
+        // Errors in the shaders themselves were already reported above
+        let modules = parse(vertex, ShaderStage::Vertex).and_then(|vertex| Ok((vertex, parse(fragment, ShaderStage::Fragment)?)));
+        if let Ok((vertex_module, fragment_module)) = modules {
+            errors.extend(check_interface(vertex, &vertex_module, fragment, &fragment_module));
+        }
+    }
+
+    // An error in an included file is reported once, not once per shader including it
+    let mut reported = HashSet::new();
+    errors.retain(|error| reported.insert(error.clone()));
+    if !errors.is_empty() {
+        for error in &errors {
+            eprintln!("{}\n", error);
+        }
+        eprintln!("{} error(s) in the shaders of {}", errors.len(), SHADER_DIRECTORY);
+        std::process::exit(1);
+    }
+}
+
+/// The paths of the files of a directory and its subdirectories, relative to src/shaders.
+fn shader_paths(directory: &Path, prefix: &str) -> Vec<String> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(directory).unwrap_or_else(|error| panic!("Could not read {}: {}", directory.display(), error)) {
+        let entry = entry.unwrap();
+        let path = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        if entry.file_type().unwrap().is_dir() {
+            paths.extend(shader_paths(&entry.path(), &format!("{}/", path)));
+        } else {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    paths
+}
+
+/// The naga stage of a shader. None for geometry shaders, which naga can't parse.
+fn naga_stage(library_shader: &LibraryShader) -> Option<ShaderStage> {
+    match shader_library::ShaderStage::of_file(&library_shader.file)? {
+        shader_library::ShaderStage::Vertex => Some(ShaderStage::Vertex),
+        shader_library::ShaderStage::Geometry => None,
+        shader_library::ShaderStage::Fragment => Some(ShaderStage::Fragment),
+        shader_library::ShaderStage::Compute => Some(ShaderStage::Compute),
+    }
+}
+
+/// Preprocess, parse and validate a shader. Errors are located in the file they come from, which
+/// may be an included one.
+fn parse(library_shader: &LibraryShader, stage: ShaderStage) -> Result<Module, String> {
+    let path = library_shader.file.path;
+    let defines = library_shader.defines.iter().fold(Defines::new(), |defines, name| defines.with(name));
+    let code = fs::read_to_string(format!("{}/{}", SHADER_DIRECTORY, path))
+        .map_err(|error| format!("{}/{}: {}", SHADER_DIRECTORY, path, error))?;
+    let preprocessed = preprocess(path, &code, &defines, LineDirectives::Numbered, &mut |include| {
+        fs::read_to_string(format!("{}/{}", SHADER_DIRECTORY, include)).map_err(|error| error.to_string())
+    }).map_err(|error| format!("{}/{}", SHADER_DIRECTORY, error))?;
+
+    let code = adapt_for_naga(&preprocessed.code);
+    // Reported like the driver's errors at runtime (see diagnostics.rs)
+    let locate = |span: Span, mut message: String| {
+        if !defines.is_empty() {
+            message.push_str(&format!(" (in {}, with {})", library_shader.name, library_shader.defines.join(", ")));
+        }
+        let mut diagnostic = Diagnostic {
+            severity: Severity::Error,
+            message,
+            file: format!("{}/{}", SHADER_DIRECTORY, path),
+            line: None,
+            column: None,
+            source_line: None,
+        };
+        if span.is_defined() {
+            let location = span.location(&code);
+            let (source, line) = original_line(&code, location.line_number as usize);
+            let source = &preprocessed.sources[source];
+            diagnostic.file = format!("{}/{}", SHADER_DIRECTORY, source.name);
+            diagnostic.line = Some(line as u32);
+            diagnostic.column = Some(location.line_position);
+            diagnostic.source_line = source.code.lines().nth(line - 1).map(String::from);
+        }
+        diagnostic.to_string()
+    };
+
+    let module = Frontend::default().parse(&Options::from(stage), &code).map_err(|errors| {
+        errors.errors.iter()
+            .map(|error| locate(error.meta, error.kind.to_string()))
+            .collect::<Vec<_>>().join("\n")
+    })?;
+    Validator::new(ValidationFlags::all(), Capabilities::all()).validate(&module).map_err(|error| {
+        let span = error.spans().next().map(|(span, _)| *span).unwrap_or_default();
+        let mut message = error.as_inner().to_string();
+        let mut source = std::error::Error::source(error.as_inner());
+        while let Some(cause) = source {
+            message.push_str(&format!(": {}", cause));
+            source = cause.source();
+        }
+        locate(span, message)
+    })?;
+    Ok(module)
+}
+
+/// The source string and line of a line of preprocessed code, from the `#line` directives before it.
+fn original_line(code: &str, line_number: usize) -> (usize, usize) {
+    let (mut source, mut line) = (0, 1);
+    for code_line in code.lines().take(line_number - 1) {
+        let directive = code_line.trim().strip_prefix("#line ").map(|directive| {
+            let mut numbers = directive.split_whitespace().map(|number| number.parse::<usize>().ok());
+            (numbers.next().flatten(), numbers.next().flatten())
+        });
+        match directive {
+            Some((Some(next_line), next_source)) => {
+                line = next_line;
+                source = next_source.unwrap_or(source);
+            }
+            _ => line += 1,
+        }
+    }
+    (source, line)
+}
+
+/// Turn desktop GLSL into the Vulkan flavor naga reads, line for line.
+fn adapt_for_naga(code: &str) -> String {
+    let mut binding = 0;
+    let mut next_binding = || {
+        binding += 1;
+        binding
+    };
+
+    code.lines().map(|line| {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("#version") {
+            String::from("#version 450 core")
+        } else if trimmed.starts_with("uniform ") {
+            format!("layout(binding = {}) {}", next_binding(), line)
+        } else if trimmed.starts_with("layout") && !trimmed.contains("binding")
+            && (trimmed.contains(") uniform ") || trimmed.contains(") buffer ")) {
+            line.replacen(')', &format!(", binding = {})", next_binding()), 1)
+        } else {
+            String::from(line)
+        }
+    }).collect::<Vec<_>>().join("\n")
+}
+
+/// A named input or output of an entry point, with its binding.
+type Varying<'a> = (Option<&'a String>, Handle<Type>, Option<&'a Binding>);
+
+/// The inputs or outputs of an entry point that go through a location, by name. Structs, such as
+/// the outputs of a vertex shader, are flattened.
+fn located_varyings(module: &Module, varyings: Vec<Varying<'_>>) -> Vec<(String, TypeInner)> {
+    let mut located = Vec::new();
+    for (name, ty, binding) in varyings {
+        match (&module.types[ty].inner, binding) {
+            (inner, Some(Binding::Location { .. })) => located.push((name.cloned().unwrap_or_default(), inner.clone())),
+            (TypeInner::Struct { members, .. }, None) => located.extend(located_varyings(module, members.iter()
+                .map(|member| (member.name.as_ref(), member.ty, member.binding.as_ref()))
+                .collect())),
+            _ => {}
+        }
+    }
+    located
+}
+
+/// Every input of the fragment shader must be an output of the vertex shader, of the same type.
+fn check_interface(vertex: &LibraryShader, vertex_module: &Module, fragment: &LibraryShader, fragment_module: &Module) -> Vec<String> {
+    let outputs = located_varyings(vertex_module, vertex_module.entry_points[0].function.result.iter()
+        .map(|result| (None, result.ty, result.binding.as_ref()))
+        .collect());
+    let inputs = located_varyings(fragment_module, fragment_module.entry_points[0].function.arguments.iter()
+        .map(|argument| (argument.name.as_ref(), argument.ty, argument.binding.as_ref()))
+        .collect());
+
+    inputs.iter().filter_map(|(name, input_type)| {
+        let message = match outputs.iter().find(|(output_name, _)| output_name == name) {
+            None => format!("the input {} of {} is not an output of {}", name, fragment.name, vertex.name),
+            Some((_, output_type)) if output_type != input_type => format!("the input {} of {} is a {}, but {} outputs a {}",
+                name, fragment.name, type_name(input_type), vertex.name, type_name(output_type)),
+            Some(_) => return None,
+        };
+        Some(Diagnostic {
+            severity: Severity::Error,
+            message,
+            file: format!("{}/{}", SHADER_DIRECTORY, fragment.file.path),
+            line: None,
+            column: None,
+            source_line: None,
+        }.to_string())
+    }).collect()
+}
+
+/// The GLSL name of a type, such as vec3.
+fn type_name(ty: &TypeInner) -> String {
+    let prefix = |scalar: &naga::Scalar| match scalar.kind {
+        naga::ScalarKind::Sint => "i",
+        naga::ScalarKind::Uint => "u",
+        naga::ScalarKind::Bool => "b",
+        _ => "",
+    };
+    match ty {
+        TypeInner::Scalar(scalar) => String::from(match scalar.kind {
+            naga::ScalarKind::Sint => "int",
+            naga::ScalarKind::Uint => "uint",
+            naga::ScalarKind::Bool => "bool",
+            _ => "float",
+        }),
+        TypeInner::Vector { size, scalar } => format!("{}vec{}", prefix(scalar), *size as u8),
+        TypeInner::Matrix { columns, rows, .. } => format!("mat{}x{}", *columns as u8, *rows as u8),
+        other => format!("{:?}", other),
+    }
+}
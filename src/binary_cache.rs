@@ -0,0 +1,245 @@
+/*
+An on-disk cache of linked programs, so they are not compiled again at every launch.
+
+Compiling and linking every program at startup gets slow as the exercises and their variants add
+up, especially on llvmpipe. Once linked, a program can be saved in the driver's own binary format
+(`glGetProgramBinary`, OpenGL 4.1 or GL_ARB_get_program_binary) and loaded back as is
+(`glProgramBinary`), which skips both steps.
+
+A binary only makes sense to the driver that produced it, so the entries are keyed by the driver's
+vendor, renderer and version strings, along with the preprocessed code and the defines of every
+stage. Changing a shader or updating the driver changes the key, and the old entry is simply never
+read again. The driver can still reject a binary, in which case the entry is deleted and the
+program compiled as usual.
+
+The cache is off unless the host enables it, see `enable` and `--program-cache`. Like the GL
+context it works with, it is enabled for a single thread.
+ */
+
+use std::cell::RefCell;
+use std::ffi::{c_void, CStr};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::preprocessor::{Defines, PreprocessedSource};
+use crate::shader::{ShaderSource, ShaderStage};
+
+thread_local! {
+    /// The cache the `ShaderProgram`s of the thread are loaded from and saved to, if any.
+    static CACHE: RefCell<Option<ProgramBinaryCache>> = const { RefCell::new(None) };
+}
+
+/// Save the programs linked from now on by the calling thread into `directory`, and load them
+/// from it instead of compiling them when they are already there. The directory is created when
+/// needed.
+pub fn enable(directory: &Path) {
+    CACHE.with_borrow_mut(|cache| *cache = Some(ProgramBinaryCache::new(directory)));
+}
+
+/// Stop saving and loading the programs of the calling thread.
+pub fn disable() {
+    CACHE.with_borrow_mut(|cache| *cache = None);
+}
+
+/// The cache enabled with `enable` on this thread, when the current context can use it.
+pub(crate) fn enabled_cache() -> Option<ProgramBinaryCache> {
+    CACHE.with_borrow(|cache| cache.clone()).filter(|_| ProgramBinaryCache::is_supported())
+}
+
+/// The key of the program linked from `sources`, see `ProgramBinaryCache::key`.
+///
+/// # Arguments
+/// * `sources` - The shaders of the program
+/// * `preprocessed` - The code of each of them once preprocessed, in the same order
+pub fn program_key(sources: &[ShaderSource], preprocessed: &[PreprocessedSource]) -> u64 {
+    let stages: Vec<_> = sources.iter().zip(preprocessed)
+        .map(|(source, preprocessed)| (source.stage, &source.defines, preprocessed.code.as_str()))
+        .collect();
+    ProgramBinaryCache::key(&stages)
+}
+
+/// Linked programs saved in a directory, one file per program.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProgramBinaryCache {
+    directory: PathBuf,
+}
+
+/// What a program is built from: the stage, defines and preprocessed code of each of its shaders.
+pub type ProgramStages<'a> = [(ShaderStage, &'a Defines, &'a str)];
+
+impl ProgramBinaryCache {
+    pub fn new(directory: &Path) -> ProgramBinaryCache {
+        ProgramBinaryCache { directory: directory.to_path_buf() }
+    }
+
+    /// Whether the current context can save programs and load them back.
+    pub fn is_supported() -> bool {
+        if !gl::GetProgramBinary::is_loaded() || !gl::ProgramBinary::is_loaded() {
+            return false;
+        }
+        !binary_formats().is_empty()
+    }
+
+    /// The key of a program in the cache, for the driver of the current context.
+    pub fn key(stages: &ProgramStages) -> u64 {
+        let mut hash = FNV_OFFSET_BASIS;
+        for driver_string in [gl::VENDOR, gl::RENDERER, gl::VERSION] {
+            hash = fnv1a(hash, get_string(driver_string).as_bytes());
+        }
+        for (stage, defines, code) in stages {
+            hash = fnv1a(hash, stage.to_string().as_bytes());
+            for (name, value) in defines.iter() {
+                hash = fnv1a(fnv1a(hash, name.as_bytes()), value.as_bytes());
+            }
+            hash = fnv1a(hash, code.as_bytes());
+        }
+        hash
+    }
+
+    /// Create a program from the binary saved under `key`. None when there is none, or when the
+    /// driver rejects it, in which case the entry is deleted.
+    pub fn load(&self, key: u64) -> Option<u32> {
+        let path = self.path(key);
+        let data = fs::read(&path).ok()?;
+
+        // The file starts with the format of the binary
+        let format = data.get(..4).map(|format| u32::from_le_bytes(format.try_into().unwrap()));
+        let program = format.filter(|format| binary_formats().contains(format)).and_then(|format| unsafe {
+            let program = gl::CreateProgram();
+            gl::ProgramBinary(program, format, data[4..].as_ptr() as *const c_void, (data.len() - 4) as i32);
+            let mut success = 0;
+            gl::GetProgramiv(program, gl::LINK_STATUS, &mut success);
+            if success == 0 {
+                gl::DeleteProgram(program);
+                return None;
+            }
+            Some(program)
+        });
+
+        if program.is_none() {
+            let _ = fs::remove_file(&path);
+        }
+        program
+    }
+
+    /// Save a linked program under `key`.
+    ///
+    /// # Arguments
+    /// * `key` - See `ProgramBinaryCache::key`
+    /// * `program` - A program linked with gl::PROGRAM_BINARY_RETRIEVABLE_HINT set, which some
+    ///   drivers need to keep its binary around
+    pub fn store(&self, key: u64, program: u32) -> Result<(), String> {
+        let mut length = 0;
+        unsafe { gl::GetProgramiv(program, gl::PROGRAM_BINARY_LENGTH, &mut length); }
+        if length <= 0 {
+            return Err(String::from("The driver has no binary for the program"));
+        }
+
+        let mut format = 0;
+        let mut written = 0;
+        let mut binary = vec![0u8; length as usize];
+        unsafe {
+            gl::GetProgramBinary(program, length, &mut written, &mut format, binary.as_mut_ptr() as *mut c_void);
+        }
+        binary.truncate(written.max(0) as usize);
+
+        let mut data = format.to_le_bytes().to_vec();
+        data.extend(binary);
+        fs::create_dir_all(&self.directory)
+            .map_err(|error| format!("Could not create {}: {}", self.directory.display(), error))?;
+        // Written aside then renamed, so another instance never reads half of it. Each instance
+        // has its own temporary file, in case two of them save the same program at once
+        let path = self.path(key);
+        let temporary_path = path.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&temporary_path, data)
+            .and_then(|_| fs::rename(&temporary_path, &path))
+            .map_err(|error| format!("Could not write {}: {}", path.display(), error))
+    }
+
+    /// The file the program saved under `key` is in, whether it is there or not.
+    pub fn path(&self, key: u64) -> PathBuf {
+        self.directory.join(format!("{:016x}.bin", key))
+    }
+}
+
+/// The binary formats the driver can load.
+fn binary_formats() -> Vec<u32> {
+    let mut format_count = 0;
+    unsafe { gl::GetIntegerv(gl::NUM_PROGRAM_BINARY_FORMATS, &mut format_count); }
+    let mut formats = vec![0i32; format_count.max(0) as usize];
+    if !formats.is_empty() {
+        unsafe { gl::GetIntegerv(gl::PROGRAM_BINARY_FORMATS, formats.as_mut_ptr()); }
+    }
+    formats.into_iter().map(|format| format as u32).collect()
+}
+
+fn get_string(name: u32) -> String {
+    let string = unsafe { gl::GetString(name) };
+    if string.is_null() {
+        return String::new();
+    }
+    unsafe { CStr::from_ptr(string as *const _) }.to_string_lossy().into_owned()
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+
+/// 64 bits FNV-1a, which unlike `DefaultHasher` gives the same keys with every version of Rust.
+/// Every field ends with a 0 byte, so moving bytes from one field to the next changes the key.
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().chain(&[0]).fold(hash, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use crate::context_config::ContextConfig;
+    use crate::headless::HeadlessContext;
+    use crate::shader::{ShaderProgram, ShaderSource};
+    use super::*;
+
+    const VERTEX_SHADER: &str = "#version 330 core\nvoid main() { gl_Position = vec4(0.0); }\n";
+    const FRAGMENT_SHADER: &str = "#version 330 core\nout vec4 FragColor;\nuniform vec4 customColor;\n\
+        void main() { FragColor = customColor; }\n";
+
+    #[test]
+    fn programs_are_loaded_back_and_rejected_binaries_deleted() {
+        let _context = HeadlessContext::new(&ContextConfig::default(), 1, 1).unwrap();
+        if !ProgramBinaryCache::is_supported() {
+            eprintln!("The driver can't save programs, skipping");
+            return;
+        }
+        let directory = std::env::temp_dir().join(format!("program-binary-cache-{}", std::process::id()));
+        let cache = ProgramBinaryCache::new(&directory);
+
+        let no_defines = Defines::new();
+        let key = ProgramBinaryCache::key(&[
+            (ShaderStage::Vertex, &no_defines, VERTEX_SHADER),
+            (ShaderStage::Fragment, &no_defines, FRAGMENT_SHADER),
+        ]);
+        let yellow = Defines::new().with("YELLOW");
+        assert_ne!(key, ProgramBinaryCache::key(&[
+            (ShaderStage::Vertex, &no_defines, VERTEX_SHADER),
+            (ShaderStage::Fragment, &yellow, FRAGMENT_SHADER),
+        ]));
+        assert_eq!(cache.load(key), None);
+
+        let program = ShaderProgram::new(&[
+            ShaderSource::new("position.vert", VERTEX_SHADER, ShaderStage::Vertex),
+            ShaderSource::new("uniform.frag", FRAGMENT_SHADER, ShaderStage::Fragment),
+        ]).unwrap();
+        cache.store(key, program.id()).unwrap();
+
+        let loaded = cache.load(key).unwrap();
+        let location = unsafe { gl::GetUniformLocation(loaded, c"customColor".as_ptr()) };
+        assert!(location >= 0);
+        unsafe { gl::DeleteProgram(loaded); }
+
+        // A binary the driver doesn't understand, as after an update
+        let path = directory.join(format!("{:016x}.bin", key));
+        let mut data = fs::read(&path).unwrap();
+        data.truncate(data.len() / 2);
+        fs::write(&path, data).unwrap();
+        assert_eq!(cache.load(key), None);
+        assert!(!path.exists());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,99 @@
+/*
+Geometry shaders run between the vertex and the fragment shader: they receive a whole primitive
+(a point, a line or a triangle) and can emit any number of new primitives from it.
+
+Here only 4 points are drawn, and the geometry shader turns each of them into a little house: a
+square made of 2 triangles with a triangular roof on top.
+ */
+
+use bytemuck::{Pod, Zeroable};
+use crate::frame_clock::FrameTime;
+use crate::scene::Scene;
+use crate::shader::{ShaderError, ShaderProgram};
+use crate::vertex_buffer::{BufferUsage, VertexBuffer};
+use crate::vertex_layout;
+
+
+/// Each point is a 2D position followed by a color
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct ColoredPoint {
+    position: [f32; 2],
+    color: [f32; 3],
+}
+
+vertex_layout!(ColoredPoint { position: 0, color: 1 });
+
+
+#[derive(Default)]
+pub struct Houses {
+    vertex_array_object: u32,
+    vertex_buffer: Option<VertexBuffer<ColoredPoint>>,
+    shader_program: Option<ShaderProgram>,
+}
+
+impl Scene for Houses {
+    fn setup(&mut self) -> Result<(), ShaderError> {
+        unsafe {
+            gl::GenVertexArrays(1, &mut self.vertex_array_object);
+            gl::BindVertexArray(self.vertex_array_object);
+
+            let vertex_buffer = VertexBuffer::new(&[
+                ColoredPoint { position: [-0.5, 0.5], color: [1.0, 0.0, 0.0] },
+                ColoredPoint { position: [0.5, 0.5], color: [0.0, 1.0, 0.0] },
+                ColoredPoint { position: [0.5, -0.5], color: [0.0, 0.0, 1.0] },
+                ColoredPoint { position: [-0.5, -0.5], color: [1.0, 1.0, 0.0] },
+            ], BufferUsage::Static);
+            vertex_buffer.enable_attributes();
+            self.vertex_buffer = Some(vertex_buffer);
+
+            // The geometry shader is simply one more stage linked into the program
+            let shader_program = ShaderProgram::from_library(&["basic/point_color", "geometry/house", "color/interpolated"])?;
+            shader_program.use_program();
+            self.shader_program = Some(shader_program);
+        }
+        Ok(())
+    }
+
+    fn frame(&mut self, _time: &FrameTime) {
+        unsafe {
+            gl::ClearColor(0.3, 0.3, 0.3, 1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+
+            self.shader_program.as_ref().unwrap().use_program();
+            gl::BindVertexArray(self.vertex_array_object);
+            // Points, not triangles: the geometry shader builds the triangles
+            gl::DrawArrays(gl::POINTS, 0, 4);
+        }
+    }
+
+    fn teardown(&mut self) {
+        unsafe {
+            self.shader_program = None;
+            self.vertex_buffer = None;
+            gl::DeleteVertexArrays(1, &self.vertex_array_object);
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use crate::pixel_probe::{assert_pixels, Probe, DEFAULT_TOLERANCE};
+    use super::Houses;
+
+    const BACKGROUND: [f32; 3] = [0.3, 0.3, 0.3];
+
+    #[test]
+    fn every_point_becomes_a_house() {
+        assert_pixels(&mut Houses::default(), 0, &[
+            // The walls, centered on the points
+            Probe::ndc(-0.5, 0.5, [1.0, 0.0, 0.0]),
+            Probe::ndc(0.5, 0.5, [0.0, 1.0, 0.0]),
+            Probe::ndc(0.5, -0.5, [0.0, 0.0, 1.0]),
+            Probe::ndc(-0.5, -0.5, [1.0, 1.0, 0.0]),
+            // Between the houses, and on the sides of a roof
+            Probe::ndc(0.0, 0.0, BACKGROUND),
+            Probe::ndc(-0.65, 0.85, BACKGROUND),
+        ], DEFAULT_TOLERANCE);
+    }
+}
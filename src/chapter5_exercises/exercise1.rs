@@ -2,83 +2,87 @@
 Try to draw 2 triangles next to eaech other using glDrawArrays by adding more vertices to your data
 */
 
-use std::ffi::c_void;
-use crate::utils::load_shader;
-use crate::utils::validate_shader_program_linkage;
+use crate::frame_clock::FrameTime;
+use crate::scene::Scene;
+use crate::shader::{ShaderError, ShaderProgram};
+use crate::vertex_buffer::{BufferUsage, PositionVertex, VertexBuffer};
 
-const FIRST_SHADER_VERTEX: &str = include_str!("../shaders/vertex/first_shader.vert");
-const FIRST_SHADER_FRAGMENT: &str = include_str!("../shaders/fragment/first_shader.frag");
 
-
-unsafe fn create_vertex_buffer_object() -> u32 {
-    let vertices: [f32; 18] = [
-        -0.75, 0.0, 0.0,
-        -0.25, 0.0, 0.0,
-        -0.5, 0.25, 0.0,
-        0.25, 0.0, 0.0,
-        0.75, 0.0, 0.0,
-        0.5, 0.25, 0.0
-    ];
-
-    // Vertex Buffer Object (VBO) is the object that contains all the vertices which are
-    // passed to the GPU. Moving memory from the CPU to the GPU is costly therefore we
-    // should do it all at once.
-    let mut vertex_buffer_object: u32 = 0;
-    gl::GenBuffers(1, &mut vertex_buffer_object);
-    gl::BindBuffer(gl::ARRAY_BUFFER, vertex_buffer_object);
-    gl::BufferData(
-        gl::ARRAY_BUFFER,
-        (vertices.len() * std::mem::size_of::<f32>()) as isize,
-        vertices.as_ptr() as *const c_void, gl::STATIC_DRAW);
-    gl::VertexAttribPointer(
-        0,
-        3,
-        gl::FLOAT,
-        gl::FALSE,
-        3 * std::mem::size_of::<f32>() as i32, std::ptr::null() as *const c_void,
-    );
-    gl::EnableVertexAttribArray(0);
-
-    vertex_buffer_object
+#[derive(Default)]
+pub struct Exercise1 {
+    vertex_array_object: u32,
+    vertex_buffer: Option<VertexBuffer<PositionVertex>>,
+    shader_program: Option<ShaderProgram>,
 }
 
+impl Scene for Exercise1 {
+    fn setup(&mut self) -> Result<(), ShaderError> {
+        // VAO - contains the attributes and the VBO to read the vertices from.
+        // It must be bound before the attributes are set, the core profile has no default
+        // VAO to fall back to.
+        unsafe {
+            gl::GenVertexArrays(1, &mut self.vertex_array_object);
+            gl::BindVertexArray(self.vertex_array_object);
+        }
 
-pub fn main(on_loop_start: impl Fn(), on_loop_end: impl Fn()) {
-    let vertex_shader = load_shader(FIRST_SHADER_VERTEX, gl::VERTEX_SHADER);
-    let fragment_shader = load_shader(FIRST_SHADER_FRAGMENT, gl::FRAGMENT_SHADER);
-
-    unsafe {
-        create_vertex_buffer_object();
-
-        // VAO - contains the attributes and the VBO to read the vertices from
-        let mut vertex_array_object: u32 = 0;
-        gl::GenVertexArrays(0, &mut vertex_array_object);
-        gl::BindVertexArray(vertex_array_object);
+        // Vertex Buffer Object (VBO) is the object that contains all the vertices which are
+        // passed to the GPU. Moving memory from the CPU to the GPU is costly therefore we
+        // should do it all at once.
+        let vertex_buffer = VertexBuffer::new(&[
+            [-0.75, 0.0, 0.0],
+            [-0.25, 0.0, 0.0],
+            [-0.5, 0.25, 0.0],
+            [0.25, 0.0, 0.0],
+            [0.75, 0.0, 0.0],
+            [0.5, 0.25, 0.0],
+        ].map(|position| PositionVertex { position }), BufferUsage::Static);
+        // Each vertex is only a position, read by the attribute at location 0
+        vertex_buffer.enable_attributes();
+        self.vertex_buffer = Some(vertex_buffer);
 
         // a "program" is the product of linking all the relevant shaders together.
-        let shader_program = gl::CreateProgram();
-        gl::AttachShader(shader_program, vertex_shader);
-        gl::AttachShader(shader_program, fragment_shader);
-        gl::LinkProgram(shader_program);
-        validate_shader_program_linkage(shader_program);
-        gl::UseProgram(shader_program);
-
-        // Once the program is linked, we can delete the shaders
-        gl::DeleteShader(vertex_shader);
-        gl::DeleteShader(fragment_shader);
-
-        loop {
-            on_loop_start();
+        let shader_program = ShaderProgram::from_library(&["basic/position", "color/orange"])?;
+        shader_program.use_program();
+        self.shader_program = Some(shader_program);
+        Ok(())
+    }
 
+    fn frame(&mut self, _time: &FrameTime) {
+        unsafe {
             // Set the background color to blue
             gl::ClearColor(0.3, 0.3, 0.3, 1.0);
             gl::Clear(gl::COLOR_BUFFER_BIT);
 
-            gl::UseProgram(shader_program);
-            gl::BindVertexArray(vertex_array_object);
+            self.shader_program.as_ref().unwrap().use_program();
+            gl::BindVertexArray(self.vertex_array_object);
             gl::DrawArrays(gl::TRIANGLES, 0, 6);
-
-            on_loop_end();
         }
     }
-}
\ No newline at end of file
+
+    fn teardown(&mut self) {
+        self.shader_program = None;
+        self.vertex_buffer = None;
+        unsafe { gl::DeleteVertexArrays(1, &self.vertex_array_object); }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use crate::pixel_probe::{assert_pixels, Probe, DEFAULT_TOLERANCE};
+    use super::Exercise1;
+
+    const ORANGE: [f32; 3] = [1.0, 0.5, 0.2];
+    const BACKGROUND: [f32; 3] = [0.3, 0.3, 0.3];
+
+    #[test]
+    fn draws_two_orange_triangles() {
+        assert_pixels(&mut Exercise1::default(), 0, &[
+            // Centroids of the left and right triangles
+            Probe::ndc(-0.5, 0.25 / 3.0, ORANGE),
+            Probe::ndc(0.5, 0.25 / 3.0, ORANGE),
+            // Between and below them
+            Probe::ndc(0.0, 0.1, BACKGROUND),
+            Probe::ndc(-0.5, -0.1, BACKGROUND),
+        ], DEFAULT_TOLERANCE);
+    }
+}
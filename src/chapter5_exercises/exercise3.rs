@@ -3,101 +3,87 @@ Create two shader programs where the second program uses a different fragment sh
 that outputs the color yello, draw both triangles again where one outputs the color yellow.
 */
 
-use std::ffi::c_void;
-use crate::utils::load_shader;
-use crate::utils::validate_shader_program_linkage;
-
-const FIRST_SHADER_VERTEX: &str = include_str!("../shaders/vertex/first_shader.vert");
-const FIRST_SHADER_FRAGMENT: &str = include_str!("../shaders/fragment/first_shader.frag");
-const YELLOW_SHADER_FRAGMENT: &str = include_str!("../shaders/fragment/yellow_shader.frag");
-
-
-unsafe fn create_vertex_buffer_object(vertices: [f32; 9]) -> u32 {
-    // Vertex Buffer Object (VBO) is the object that contains all the vertices which are
-    // passed to the GPU. Moving memory from the CPU to the GPU is costly therefore we
-    // should do it all at once.
-    let mut vertex_buffer_object: u32 = 0;
-    gl::GenBuffers(1, &mut vertex_buffer_object);
-    gl::BindBuffer(gl::ARRAY_BUFFER, vertex_buffer_object);
-    gl::BufferData(
-        gl::ARRAY_BUFFER,
-        (vertices.len() * std::mem::size_of::<f32>()) as isize,
-        vertices.as_ptr() as *const c_void, gl::STATIC_DRAW);
-    gl::VertexAttribPointer(
-        0,
-        3,
-        gl::FLOAT,
-        gl::FALSE,
-        3 * std::mem::size_of::<f32>() as i32, std::ptr::null() as *const c_void,
-    );
-    gl::EnableVertexAttribArray(0);
-
-    vertex_buffer_object
+use crate::frame_clock::FrameTime;
+use crate::scene::Scene;
+use crate::preprocessor::Defines;
+use crate::shader::{ProgramCache, ShaderError};
+use crate::vertex_buffer::{BufferUsage, PositionVertex, VertexBuffer};
+
+const ORANGE_SHADERS: [&str; 2] = ["basic/position", "color/orange"];
+// The same files, with YELLOW defined, which switches the fragment shader to yellow
+const YELLOW_SHADERS: [&str; 2] = ["basic/position", "color/yellow"];
+
+
+#[derive(Default)]
+pub struct Exercise3 {
+    vertex_array_objects: [u32; 2],
+    vertex_buffers: Vec<VertexBuffer<PositionVertex>>,
+    shader_programs: ProgramCache,
 }
 
+impl Scene for Exercise3 {
+    fn setup(&mut self) -> Result<(), ShaderError> {
+        unsafe { gl::GenVertexArrays(2, self.vertex_array_objects.as_mut_ptr()); }
+
+        let triangles = [
+            [[-0.75, 0.0, 0.0], [-0.25, 0.0, 0.0], [-0.5, 0.25, 0.0]],
+            [[0.25, 0.0, 0.0], [0.75, 0.0, 0.0], [0.5, 0.25, 0.0]],
+        ];
+        for (vertex_array_object, triangle) in self.vertex_array_objects.iter().zip(triangles) {
+            unsafe { gl::BindVertexArray(*vertex_array_object); }
+            let vertex_buffer = VertexBuffer::new(&triangle.map(|position| PositionVertex { position }),
+                                                  BufferUsage::Static);
+            vertex_buffer.enable_attributes();
+            self.vertex_buffers.push(vertex_buffer);
+        }
 
-pub fn main(on_loop_start: impl Fn(), on_loop_end: impl Fn()) {
-    let vertex_shader = load_shader(FIRST_SHADER_VERTEX, gl::VERTEX_SHADER);
-    let fragment_shader = load_shader(FIRST_SHADER_FRAGMENT, gl::FRAGMENT_SHADER);
-    let yellow_shader = load_shader(YELLOW_SHADER_FRAGMENT, gl::FRAGMENT_SHADER);
-
-    unsafe {
-        let mut vertex_array_object1: u32 = 0;
-        gl::GenVertexArrays(1, &mut vertex_array_object1);
-        gl::BindVertexArray(vertex_array_object1);
-
-        create_vertex_buffer_object([
-            -0.75, 0.0, 0.0,
-            -0.25, 0.0, 0.0,
-            -0.5, 0.25, 0.0
-        ]);
-
-        let mut vertex_array_object2: u32 = 0;
-        gl::GenVertexArrays(1, &mut vertex_array_object2);
-        gl::BindVertexArray(vertex_array_object2);
-
-        create_vertex_buffer_object([
-            0.25, 0.0, 0.0,
-            0.75, 0.0, 0.0,
-            0.5, 0.25, 0.0
-        ]);
-
-        // Create 2 programs this time
-        let shader_program1 = gl::CreateProgram();
-        let shader_program2 = gl::CreateProgram();
-
-        // Link the first program
-        gl::AttachShader(shader_program1, vertex_shader);
-        gl::AttachShader(shader_program1, fragment_shader);
-        gl::LinkProgram(shader_program1);
-        validate_shader_program_linkage(shader_program1);
-
-        // Link the second program (the one with the yellow shader).
-        gl::AttachShader(shader_program2, vertex_shader);
-        gl::AttachShader(shader_program2, yellow_shader);
-        gl::LinkProgram(shader_program2);
-        validate_shader_program_linkage(shader_program2);
-
-        // Once the program is linked, we can delete the shaders
-        gl::DeleteShader(vertex_shader);
-        gl::DeleteShader(fragment_shader);
-
-        loop {
-            on_loop_start();
+        // Create 2 programs this time, from the same files
+        for shaders in [ORANGE_SHADERS, YELLOW_SHADERS] {
+            self.shader_programs.get(&shaders, &Defines::new())?;
+        }
+        Ok(())
+    }
 
+    fn frame(&mut self, _time: &FrameTime) {
+        unsafe {
             // Set the background color to blue
             gl::ClearColor(0.3, 0.3, 0.3, 1.0);
             gl::Clear(gl::COLOR_BUFFER_BIT);
 
-            gl::UseProgram(shader_program1);
-            gl::BindVertexArray(vertex_array_object1);
+            // Both programs are already built, so getting them is only a lookup
+            self.shader_programs.get(&ORANGE_SHADERS, &Defines::new()).unwrap().use_program();
+            gl::BindVertexArray(self.vertex_array_objects[0]);
             gl::DrawArrays(gl::TRIANGLES, 0, 3);
 
-            gl::UseProgram(shader_program2);
-            gl::BindVertexArray(vertex_array_object2);
+            self.shader_programs.get(&YELLOW_SHADERS, &Defines::new()).unwrap().use_program();
+            gl::BindVertexArray(self.vertex_array_objects[1]);
             gl::DrawArrays(gl::TRIANGLES, 0, 3);
-
-            on_loop_end();
         }
     }
-}
\ No newline at end of file
+
+    fn teardown(&mut self) {
+        self.shader_programs.clear();
+        self.vertex_buffers.clear();
+        unsafe { gl::DeleteVertexArrays(2, self.vertex_array_objects.as_ptr()); }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use crate::pixel_probe::{assert_pixels, Probe, DEFAULT_TOLERANCE};
+    use super::Exercise3;
+
+    const ORANGE: [f32; 3] = [1.0, 0.5, 0.2];
+    const YELLOW: [f32; 3] = [1.0, 1.0, 0.0];
+    const BACKGROUND: [f32; 3] = [0.3, 0.3, 0.3];
+
+    #[test]
+    fn draws_an_orange_and_a_yellow_triangle() {
+        assert_pixels(&mut Exercise3::default(), 0, &[
+            // Centroids of the left and right triangles
+            Probe::ndc(-0.5, 0.25 / 3.0, ORANGE),
+            Probe::ndc(0.5, 0.25 / 3.0, YELLOW),
+            Probe::ndc(0.0, 0.1, BACKGROUND),
+        ], DEFAULT_TOLERANCE);
+    }
+}
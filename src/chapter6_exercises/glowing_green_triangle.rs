@@ -2,95 +2,94 @@
 Now create the same 2 triangles using two different VAOs and VBOs for their data.
 */
 
-use std::ffi::{c_void, CString};
-use crate::utils::load_shader;
-use crate::utils::validate_shader_program_linkage;
-use std::intrinsics::sinf32;
-use std::time::{SystemTime, UNIX_EPOCH};
-
-const FIRST_SHADER_VERTEX: &str = include_str!("../shaders/vertex/first_shader.vert");
-const SHADER_WITH_UNIFORM_FRAGMENT: &str = include_str!("../shaders/fragment/first_uniform_shader.frag");
-
-
-unsafe fn create_vertex_buffer_object(vertices: [f32; 9]) -> u32 {
-    // Vertex Buffer Object (VBO) is the object that contains all the vertices which are
-    // passed to the GPU. Moving memory from the CPU to the GPU is costly therefore we
-    // should do it all at once.
-    let mut vertex_buffer_object: u32 = 0;
-    gl::GenBuffers(1, &mut vertex_buffer_object);
-    gl::BindBuffer(gl::ARRAY_BUFFER, vertex_buffer_object);
-    gl::BufferData(
-        gl::ARRAY_BUFFER,
-        (vertices.len() * std::mem::size_of::<f32>()) as isize,
-        vertices.as_ptr() as *const c_void, gl::STATIC_DRAW);
-    gl::VertexAttribPointer(
-        0,
-        3,
-        gl::FLOAT,
-        gl::FALSE,
-        3 * std::mem::size_of::<f32>() as i32, std::ptr::null() as *const c_void,
-    );
-    gl::EnableVertexAttribArray(0);
-
-    vertex_buffer_object
+use crate::frame_clock::FrameTime;
+use crate::scene::Scene;
+use crate::shader::{ShaderError, ShaderProgram};
+use crate::vertex_buffer::{BufferUsage, PositionVertex, VertexBuffer};
+use std::time::Duration;
+
+
+/// Green intensity (between 0 and 1) of the triangle, glowing with the time.
+///
+/// # Arguments
+/// * `elapsed` - Time since the start of the program, see `FrameTime::elapsed`
+pub fn get_dynamic_color(elapsed: Duration) -> f32 {
+    let seed = ((elapsed.as_millis() % 10000) as f32) / 500.0 % 360.0;
+    (seed.sin() + 1.0) / 2.0
 }
 
 
-pub fn get_dynamic_color() -> f32 {
-    let current_timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
-    unsafe {
-        let seed = ((current_timestamp % 10000) as f32) / 500.0 % 360.0;
-        println!("{}", seed);
-        (sinf32(seed as f32) + 1.0) / 2.0
-    }
+#[derive(Default)]
+pub struct GlowingGreenTriangle {
+    vertex_array_object: u32,
+    vertex_buffer: Option<VertexBuffer<PositionVertex>>,
+    shader_program: Option<ShaderProgram>,
 }
 
+impl Scene for GlowingGreenTriangle {
+    fn setup(&mut self) -> Result<(), ShaderError> {
+        unsafe {
+            gl::GenVertexArrays(1, &mut self.vertex_array_object);
+            gl::BindVertexArray(self.vertex_array_object);
+        }
 
-pub fn main(on_loop_start: impl Fn(), on_loop_end: impl Fn()) {
-    let vertex_shader = load_shader(FIRST_SHADER_VERTEX, gl::VERTEX_SHADER);
-    let fragment_shader = load_shader(SHADER_WITH_UNIFORM_FRAGMENT, gl::FRAGMENT_SHADER);
-
-    unsafe {
-        let mut vertex_array_object: u32 = 0;
-        gl::GenVertexArrays(1, &mut vertex_array_object);
-        gl::BindVertexArray(vertex_array_object);
-
-        create_vertex_buffer_object([
-            -0.5, -0.5, 0.0,
-            0.0, 0.5, 0.0,
-            0.5, -0.5, 0.0
-        ]);
+        let vertex_buffer = VertexBuffer::new(&[
+            [-0.5, -0.5, 0.0],
+            [0.0, 0.5, 0.0],
+            [0.5, -0.5, 0.0],
+        ].map(|position| PositionVertex { position }), BufferUsage::Static);
+        // Each vertex is only a position, read by the attribute at location 0
+        vertex_buffer.enable_attributes();
+        self.vertex_buffer = Some(vertex_buffer);
 
         // a "program" is the product of linking all the relevant shaders together.
-        let shader_program = gl::CreateProgram();
-        gl::AttachShader(shader_program, vertex_shader);
-        gl::AttachShader(shader_program, fragment_shader);
-        gl::LinkProgram(shader_program);
-        validate_shader_program_linkage(shader_program);
-
-        let color_name = CString::new("customColor").unwrap();
-        let customColorLocation = gl::GetUniformLocation(shader_program, color_name.as_ptr());
-        gl::UseProgram(shader_program);
-        gl::Uniform4f(customColorLocation, 0.0, get_dynamic_color(), 0.0, 1.0);
+        let shader_program = ShaderProgram::from_library(&["basic/position", "color/uniform"])?;
 
-        // Once the program is linked, we can delete the shaders
-        gl::DeleteShader(vertex_shader);
-        gl::DeleteShader(fragment_shader);
-
-        loop {
-            on_loop_start();
+        shader_program.use_program();
+        shader_program.set_uniform("customColor", [0.0, get_dynamic_color(Duration::ZERO), 0.0, 1.0]);
+        self.shader_program = Some(shader_program);
+        Ok(())
+    }
 
+    fn frame(&mut self, time: &FrameTime) {
+        unsafe {
             // Set the background color to blue
             gl::ClearColor(0.3, 0.3, 0.3, 1.0);
             gl::Clear(gl::COLOR_BUFFER_BIT);
 
-            gl::UseProgram(shader_program);
-            gl::Uniform4f(customColorLocation, 0.0, get_dynamic_color(), 0.0, 1.0);
+            let shader_program = self.shader_program.as_ref().unwrap();
+            shader_program.use_program();
+            shader_program.set_uniform("customColor", [0.0, get_dynamic_color(time.elapsed), 0.0, 1.0]);
 
-            gl::BindVertexArray(vertex_array_object);
+            gl::BindVertexArray(self.vertex_array_object);
             gl::DrawArrays(gl::TRIANGLES, 0, 3);
-
-            on_loop_end();
         }
     }
-}
\ No newline at end of file
+
+    fn teardown(&mut self) {
+        self.shader_program = None;
+        self.vertex_buffer = None;
+        unsafe { gl::DeleteVertexArrays(1, &self.vertex_array_object); }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use std::time::Duration;
+    use crate::pixel_probe::{assert_pixels, Probe, DEFAULT_TOLERANCE};
+    use super::{get_dynamic_color, GlowingGreenTriangle};
+
+    const BACKGROUND: [f32; 3] = [0.3, 0.3, 0.3];
+
+    #[test]
+    fn green_follows_the_time() {
+        // Frame 30 at 60 frames per second is half a second in
+        let green = get_dynamic_color(Duration::from_millis(500));
+
+        assert_pixels(&mut GlowingGreenTriangle::default(), 30, &[
+            // Centroid of the triangle
+            Probe::ndc(0.0, -1.0 / 6.0, [0.0, green, 0.0]),
+            Probe::ndc(0.0, 0.6, BACKGROUND),
+        ], DEFAULT_TOLERANCE);
+    }
+}
@@ -1,107 +1,103 @@
 /**
 Now create the same 2 triangles using two different VAOs and VBOs for their data.
 */
-use std::ffi::{c_void, CString};
-use crate::utils::load_shader;
-use crate::utils::validate_shader_program_linkage;
-use std::intrinsics::{sinf32, size_of};
-use std::time::{SystemTime, UNIX_EPOCH};
-
-const SHADER_WITH_COLOR_INPUT_VERTEX: &str = include_str!("../shaders/vertex/shader_with_color.vert");
-const SHADER_WITH_COLOR_INPUT_FRAG: &str = include_str!("../shaders/fragment/shader_with_color_input.frag");
-
-
-unsafe fn create_vertex_buffer_object(vertices: [f32; 18]) -> u32 {
-    // This time the vertices also contain the color of each point.
-    let mut vertex_buffer_object: u32 = 0;
-    gl::GenBuffers(1, &mut vertex_buffer_object);
-    gl::BindBuffer(gl::ARRAY_BUFFER, vertex_buffer_object);
-    gl::BufferData(
-        gl::ARRAY_BUFFER,
-        (vertices.len() * std::mem::size_of::<f32>()) as isize,
-        vertices.as_ptr() as *const c_void, gl::STATIC_DRAW);
-    gl::VertexAttribPointer(
-        0,
-        3,
-        gl::FLOAT,
-        gl::FALSE,
-        6 * std::mem::size_of::<f32>() as i32,
-        0 as *const c_void,
-    );
-
-    gl::EnableVertexAttribArray(0);
-
-    // This is added for the color attribute.
-    // It's index is 1 (look at the location value inside the shader)
-    // Size is 3 floats
-    // type is FLOAT
-    // We do not normalize our colors
-    // Stride is now 6, because the GPU needs to make 6 steps in order to reach the next color.
-    // pointer should point to the start of the first color, which is the byte at index 3 * (float size)
-    gl::VertexAttribPointer(
-        1,
-        3,
-        gl::FLOAT,
-        gl::FALSE,
-        6 * std::mem::size_of::<f32>() as i32,
-        (3 * size_of::<f32>()) as *const c_void,
-    );
-
-    gl::EnableVertexAttribArray(1);
-
-    vertex_buffer_object
+use bytemuck::{Pod, Zeroable};
+use crate::frame_clock::FrameTime;
+use crate::scene::Scene;
+use crate::shader::{ShaderError, ShaderProgram};
+use crate::vertex_buffer::{BufferUsage, VertexBuffer};
+use crate::vertex_layout;
+use std::time::Duration;
+
+
+/// A value between 0 and 1 that oscillates with `elapsed`, see `FrameTime::elapsed`.
+pub fn get_dynamic_color(elapsed: Duration) -> f32 {
+    let seed = ((elapsed.as_millis() % 10000) as f32) / 500.0 % 360.0;
+    (seed.sin() + 1.0) / 2.0
 }
 
 
-pub fn get_dynamic_color() -> f32 {
-    let current_timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
-    unsafe {
-        let seed = ((current_timestamp % 10000) as f32) / 500.0 % 360.0;
-        println!("{}", seed);
-        (sinf32(seed as f32) + 1.0) / 2.0
-    }
+/// This time the vertices also contain the color of each point.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct ColoredVertex {
+    position: [f32; 3],
+    color: [f32; 3],
 }
 
+// Look at the location values inside the shader
+vertex_layout!(ColoredVertex { position: 0, color: 1 });
 
-pub fn main(on_loop_start: impl Fn(), on_loop_end: impl Fn()) {
-    let vertex_shader = load_shader(SHADER_WITH_COLOR_INPUT_VERTEX, gl::VERTEX_SHADER);
-    let fragment_shader = load_shader(SHADER_WITH_COLOR_INPUT_FRAG, gl::FRAGMENT_SHADER);
-
-    unsafe {
-        let mut vertex_array_object: u32 = 0;
-        gl::GenVertexArrays(1, &mut vertex_array_object);
-        gl::BindVertexArray(vertex_array_object);
 
-        create_vertex_buffer_object([
-            -0.5, -0.5, 0.0, 1.0, 0.0, 0.0,
-            0.0, 0.5, 0.0, 0.0, 1.0, 0.0,
-            0.5, -0.5, 0.0, 0.0, 0.0, 1.0
-        ]);
+#[derive(Default)]
+pub struct MoreAttributes {
+    vertex_array_object: u32,
+    vertex_buffer: Option<VertexBuffer<ColoredVertex>>,
+    shader_program: Option<ShaderProgram>,
+}
 
-        // a "program" is the product of linking all the relevant shaders together.
-        let shader_program = gl::CreateProgram();
-        gl::AttachShader(shader_program, vertex_shader);
-        gl::AttachShader(shader_program, fragment_shader);
-        gl::LinkProgram(shader_program);
-        validate_shader_program_linkage(shader_program);
-        gl::UseProgram(shader_program);
+impl Scene for MoreAttributes {
+    fn setup(&mut self) -> Result<(), ShaderError> {
+        unsafe {
+            gl::GenVertexArrays(1, &mut self.vertex_array_object);
+            gl::BindVertexArray(self.vertex_array_object);
+        }
 
-        // Once the program is linked, we can delete the shaders
-        gl::DeleteShader(vertex_shader);
-        gl::DeleteShader(fragment_shader);
+        let vertex_buffer = VertexBuffer::new(&[
+            ColoredVertex { position: [-0.5, -0.5, 0.0], color: [1.0, 0.0, 0.0] },
+            ColoredVertex { position: [0.0, 0.5, 0.0], color: [0.0, 1.0, 0.0] },
+            ColoredVertex { position: [0.5, -0.5, 0.0], color: [0.0, 0.0, 1.0] },
+        ], BufferUsage::Static);
+        // Each attribute is 3 floats, which are not normalized. The stride is the size of a
+        // whole vertex, and the color starts right after the position, at the byte at index
+        // 3 * (float size): all of it comes from ColoredVertex.
+        vertex_buffer.enable_attributes();
+        self.vertex_buffer = Some(vertex_buffer);
 
-        loop {
-            on_loop_start();
+        // a "program" is the product of linking all the relevant shaders together.
+        let shader_program = ShaderProgram::from_library(&["basic/position_color", "color/interpolated"])?;
+        shader_program.use_program();
+        self.shader_program = Some(shader_program);
+        Ok(())
+    }
 
+    fn frame(&mut self, _time: &FrameTime) {
+        unsafe {
             // Set the background color to blue
             gl::ClearColor(0.3, 0.3, 0.3, 1.0);
             gl::Clear(gl::COLOR_BUFFER_BIT);
 
-            gl::UseProgram(shader_program);
-            gl::BindVertexArray(vertex_array_object);
+            self.shader_program.as_ref().unwrap().use_program();
+            gl::BindVertexArray(self.vertex_array_object);
             gl::DrawArrays(gl::TRIANGLES, 0, 3);
-
-            on_loop_end();
         }
     }
-}
\ No newline at end of file
+
+    fn teardown(&mut self) {
+        self.shader_program = None;
+        self.vertex_buffer = None;
+        unsafe { gl::DeleteVertexArrays(1, &self.vertex_array_object); }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use crate::pixel_probe::{assert_pixels, Probe};
+    use super::MoreAttributes;
+
+    const BACKGROUND: [f32; 3] = [0.3, 0.3, 0.3];
+
+    /// The colors are interpolated, so the probed pixel centers are a little off the exact colors
+    const TOLERANCE: f32 = 0.02;
+
+    #[test]
+    fn interpolates_the_vertex_colors() {
+        assert_pixels(&mut MoreAttributes::default(), 0, &[
+            // The red, green and blue vertices blend equally at the centroid
+            Probe::ndc(0.0, -1.0 / 6.0, [1.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0]),
+            // Halfway along the bottom edge only red and blue are left
+            Probe::ndc(0.0, -0.49, [0.5, 0.0, 0.5]),
+            Probe::ndc(0.0, 0.6, BACKGROUND),
+        ], TOLERANCE);
+    }
+}
@@ -0,0 +1,260 @@
+/*
+Command line arguments of the binary.
+
+The arguments are parsed by hand, there are too few of them to justify pulling in a crate.
+ */
+
+use std::path::PathBuf;
+use std::str::FromStr;
+use crate::context_config::ContextConfig;
+use crate::exercises::DEFAULT_EXERCISE;
+
+pub const DEFAULT_WIDTH: u32 = 800;
+pub const DEFAULT_HEIGHT: u32 = 600;
+
+pub const USAGE: &str = "\
+Usage: opengl [OPTIONS]
+
+Options:
+    -e, --exercise <ID>        Exercise to run, such as chapter5/exercise3
+                               [default: chapter6/more_attributes]
+    -l, --list                 List all the exercises and exit
+        --list-shaders         List the shaders of the shader library and exit
+        --fixed-rate <HZ>      Advance the time by exactly 1/HZ seconds every frame, instead of
+                               following the wall clock, so the frames are reproducible
+    -h, --help                 Print this message and exit
+        --size <WxH>           Size of the window, or of the offscreen framebuffer when headless
+                               [default: 800x600]
+        --frames <N>           Render N frames and exit
+        --headless             Render offscreen without opening a window, 1 frame unless
+                               --frames is given
+        --screenshot <PATH>    Save a PNG of the frame given by --screenshot-frame
+        --screenshot-frame <N> Frame to save with --screenshot, starting at 0
+                               [default: the last one of --frames, or 0]
+        --program-cache <DIR>  Save the linked programs into DIR, and load them from it at the
+                               next launch instead of compiling them again
+
+OpenGL context options:
+        --gl-version <X.Y>     OpenGL version to request [default: 3.3]
+        --gl-profile <NAME>    core or compatibility [default: core]
+        --gl-forward-compatible
+                               Request a forward compatible context
+        --gl-debug             Request a debug context
+        --depth-bits <BITS>    Bits of the depth buffer [default: 24]
+        --stencil-bits <BITS>  Bits of the stencil buffer [default: 8]
+        --msaa <SAMPLES>       Samples per pixel, 0 turns MSAA off [default: 0]
+        --vsync <MODE>         on, off or adaptive [default: on]";
+
+pub struct Arguments {
+    /// Id of the exercise to run, such as "chapter5/exercise3".
+    pub exercise: String,
+    /// Steps per second of a `FixedStepClock` to drive the frames with, instead of the wall
+    /// clock.
+    pub fixed_rate: Option<u32>,
+    /// Size in pixels of the window, or of the offscreen framebuffer when headless.
+    pub width: u32,
+    pub height: u32,
+    /// Number of frames to render before exiting, forever if not given.
+    pub frames: Option<u64>,
+    /// Render into an offscreen framebuffer, without a window.
+    pub headless: bool,
+    /// Where to save a PNG of the frame `screenshot_frame`.
+    pub screenshot: Option<PathBuf>,
+    pub screenshot_frame: Option<u64>,
+    /// Directory of the program binary cache, which is off if not given.
+    pub program_cache: Option<PathBuf>,
+    /// The OpenGL context to request.
+    pub context: ContextConfig,
+    /// List the exercises instead of running one.
+    pub list: bool,
+    /// List the shaders of the library instead of running an exercise.
+    pub list_shaders: bool,
+    /// Print the usage instead of running an exercise.
+    pub help: bool,
+}
+
+impl Arguments {
+    /// Parse the command line arguments, without the program name.
+    ///
+    /// Both `--option value` and `--option=value` forms are accepted.
+    pub fn parse(args: impl IntoIterator<Item=String>) -> Result<Arguments, String> {
+        let mut arguments = Arguments {
+            exercise: String::from(DEFAULT_EXERCISE),
+            fixed_rate: None,
+            width: DEFAULT_WIDTH,
+            height: DEFAULT_HEIGHT,
+            frames: None,
+            headless: false,
+            screenshot: None,
+            screenshot_frame: None,
+            program_cache: None,
+            context: ContextConfig::default(),
+            list: false,
+            list_shaders: false,
+            help: false,
+        };
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (option, inline_value) = match arg.split_once('=') {
+                Some((option, value)) => (option.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+
+            match option.as_str() {
+                "-e" | "--exercise" => {
+                    arguments.exercise = take_value(&option, inline_value, &mut args)?;
+                }
+                "--fixed-rate" => {
+                    let value = take_value(&option, inline_value, &mut args)?;
+                    arguments.fixed_rate = Some(parse_positive(&option, &value)?);
+                }
+                "--size" => {
+                    let value = take_value(&option, inline_value, &mut args)?;
+                    (arguments.width, arguments.height) = parse_size(&option, &value)?;
+                }
+                "--frames" => {
+                    let value = take_value(&option, inline_value, &mut args)?;
+                    arguments.frames = Some(parse_positive(&option, &value)? as u64);
+                }
+                "--headless" => arguments.headless = true,
+                "--screenshot" => {
+                    arguments.screenshot = Some(PathBuf::from(take_value(&option, inline_value, &mut args)?));
+                }
+                "--program-cache" => {
+                    arguments.program_cache = Some(PathBuf::from(take_value(&option, inline_value, &mut args)?));
+                }
+                "--screenshot-frame" => {
+                    let value = take_value(&option, inline_value, &mut args)?;
+                    arguments.screenshot_frame = Some(parse_number(&option, &value)?);
+                }
+                "-l" | "--list" => arguments.list = true,
+                "--list-shaders" => arguments.list_shaders = true,
+                "-h" | "--help" => arguments.help = true,
+                "--gl-version" => {
+                    let value = take_value(&option, inline_value, &mut args)?;
+                    let (major_version, minor_version) = ContextConfig::parse_version(&value)?;
+                    arguments.context.major_version = major_version;
+                    arguments.context.minor_version = minor_version;
+                }
+                "--gl-profile" => {
+                    arguments.context.profile = take_value(&option, inline_value, &mut args)?.parse()?;
+                }
+                "--gl-forward-compatible" => arguments.context.forward_compatible = true,
+                "--gl-debug" => arguments.context.debug = true,
+                "--depth-bits" => {
+                    let value = take_value(&option, inline_value, &mut args)?;
+                    arguments.context.depth_bits = parse_number(&option, &value)?;
+                }
+                "--stencil-bits" => {
+                    let value = take_value(&option, inline_value, &mut args)?;
+                    arguments.context.stencil_bits = parse_number(&option, &value)?;
+                }
+                "--msaa" => {
+                    let value = take_value(&option, inline_value, &mut args)?;
+                    arguments.context.msaa_samples = parse_number(&option, &value)?;
+                }
+                "--vsync" => {
+                    arguments.context.swap_interval = take_value(&option, inline_value, &mut args)?.parse()?;
+                }
+                _ => return Err(format!("Unknown argument '{}'", option)),
+            }
+        }
+
+        // Headless runs render a single frame by default
+        let frame_count = arguments.frames.or(arguments.headless.then_some(1));
+        if let (Some(screenshot_frame), Some(frame_count)) = (arguments.screenshot_frame, frame_count) {
+            if screenshot_frame >= frame_count {
+                return Err(format!("Frame {} of --screenshot-frame is never rendered, only frames 0 to {} are",
+                                   screenshot_frame, frame_count - 1));
+            }
+        }
+
+        Ok(arguments)
+    }
+
+    /// Index of the frame `--screenshot` saves, None when no screenshot was asked for.
+    pub fn screenshot_frame_index(&self) -> Option<u64> {
+        self.screenshot.as_ref()?;
+        Some(self.screenshot_frame.unwrap_or(self.frames.map_or(0, |frames| frames - 1)))
+    }
+}
+
+/// The value of an option, either given inline (`--option=value`) or as the next argument.
+fn take_value(option: &str,
+              inline_value: Option<String>,
+              args: &mut impl Iterator<Item=String>) -> Result<String, String> {
+    inline_value
+        .or_else(|| args.next())
+        .ok_or(format!("Missing value for {}", option))
+}
+
+fn parse_number<T: FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value.parse::<T>()
+        .map_err(|_| format!("Invalid value '{}' for {}, expected a number", value, option))
+}
+
+fn parse_positive(option: &str, value: &str) -> Result<u32, String> {
+    value.parse::<u32>()
+        .ok()
+        .filter(|number| *number > 0)
+        .ok_or(format!("Invalid value '{}' for {}, expected a positive integer", value, option))
+}
+
+/// Parse a size such as "800x600".
+fn parse_size(option: &str, value: &str) -> Result<(u32, u32), String> {
+    value.split_once('x')
+        .and_then(|(width, height)| Some((parse_positive(option, width).ok()?,
+                                          parse_positive(option, height).ok()?)))
+        .ok_or(format!("Invalid size '{}' for {}, expected <width>x<height>", value, option))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Arguments, String> {
+        Arguments::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn options_take_their_value_inline_or_next() {
+        let arguments = parse(&["-e", "chapter5/exercise3", "--size=640x480", "--frames", "10", "--gl-version=4.3"]).unwrap();
+        assert_eq!(arguments.exercise, "chapter5/exercise3");
+        assert_eq!((arguments.width, arguments.height), (640, 480));
+        assert_eq!(arguments.frames, Some(10));
+        assert_eq!((arguments.context.major_version, arguments.context.minor_version), (4, 3));
+
+        let arguments = parse(&[]).unwrap();
+        assert_eq!(arguments.exercise, DEFAULT_EXERCISE);
+        assert_eq!((arguments.width, arguments.height), (DEFAULT_WIDTH, DEFAULT_HEIGHT));
+        assert_eq!(arguments.frames, None);
+    }
+
+    #[test]
+    fn invalid_arguments_are_errors() {
+        assert_eq!(parse(&["--frobnicate"]).err().unwrap(), "Unknown argument '--frobnicate'");
+        assert_eq!(parse(&["--exercise"]).err().unwrap(), "Missing value for --exercise");
+        assert_eq!(parse(&["--frames", "0"]).err().unwrap(), "Invalid value '0' for --frames, expected a positive integer");
+        assert_eq!(parse(&["--size", "800"]).err().unwrap(), "Invalid size '800' for --size, expected <width>x<height>");
+        assert_eq!(parse(&["--msaa=four"]).err().unwrap(), "Invalid value 'four' for --msaa, expected a number");
+    }
+
+    #[test]
+    fn screenshots_default_to_the_last_frame() {
+        assert_eq!(parse(&["--frames", "30"]).unwrap().screenshot_frame_index(), None);
+        assert_eq!(parse(&["--screenshot", "a.png", "--frames", "30"]).unwrap().screenshot_frame_index(), Some(29));
+        assert_eq!(parse(&["--screenshot", "a.png", "--headless"]).unwrap().screenshot_frame_index(), Some(0));
+        let arguments = parse(&["--screenshot", "a.png", "--frames", "30", "--screenshot-frame", "12"]).unwrap();
+        assert_eq!(arguments.screenshot_frame_index(), Some(12));
+    }
+
+    #[test]
+    fn screenshot_frames_must_be_rendered() {
+        assert_eq!(parse(&["--screenshot", "a.png", "--frames", "30", "--screenshot-frame", "30"]).err().unwrap(),
+                   "Frame 30 of --screenshot-frame is never rendered, only frames 0 to 29 are");
+        assert!(parse(&["--screenshot", "a.png", "--headless", "--screenshot-frame", "1"]).is_err());
+        // Windowed runs without --frames go on until they are closed
+        assert!(parse(&["--screenshot", "a.png", "--screenshot-frame", "500"]).is_ok());
+    }
+}
@@ -0,0 +1,139 @@
+/*
+Time sources for the frame clock.
+
+Exercises never read the system time themselves, they get it from the `FrameClock`, which in
+turn reads it from a `Clock`. Swapping the clock makes time-driven exercises reproducible:
+with a `FixedStepClock` at 60 Hz, frame 120 is always drawn at exactly 2 seconds, no matter
+how long it really took to get there.
+ */
+
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+pub trait Clock {
+    /// Time since the clock started. Called exactly once per frame by the `FrameClock`.
+    fn now(&mut self) -> Duration;
+}
+
+/// Follows the wall clock.
+pub struct RealClock {
+    start: Instant,
+}
+
+impl RealClock {
+    pub fn new() -> RealClock {
+        RealClock { start: Instant::now() }
+    }
+}
+
+impl Default for RealClock {
+    fn default() -> Self {
+        RealClock::new()
+    }
+}
+
+impl Clock for RealClock {
+    fn now(&mut self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// Advances by exactly one step every time it is read, ignoring the wall clock.
+pub struct FixedStepClock {
+    rate: u64,
+    next_frame: u64,
+}
+
+impl FixedStepClock {
+    /// # Arguments
+    /// * `rate` - Number of steps per second, such as 60
+    pub fn new(rate: u32) -> FixedStepClock {
+        FixedStepClock::starting_at_frame(rate, 0)
+    }
+
+    /// Create a clock whose first reading is the time of `frame`, so a single frame in the
+    /// middle of an animation can be reproduced without drawing all the frames before it.
+    ///
+    /// # Arguments
+    /// * `rate` - Number of steps per second, such as 60
+    /// * `frame` - Index of the first frame
+    pub fn starting_at_frame(rate: u32, frame: u64) -> FixedStepClock {
+        assert!(rate > 0, "The clock rate must be positive");
+        FixedStepClock {
+            rate: rate as u64,
+            next_frame: frame,
+        }
+    }
+}
+
+impl Clock for FixedStepClock {
+    fn now(&mut self) -> Duration {
+        // Computed from the frame index rather than by summing steps, so rounding errors do not
+        // accumulate and frame 120 at 60 Hz is exactly 2 seconds.
+        let now = Duration::from_nanos(self.next_frame * 1_000_000_000 / self.rate);
+        self.next_frame += 1;
+        now
+    }
+}
+
+/// Only moves when told to. Clones share the same time, so one clone can be handed to the
+/// `FrameClock` while another is kept to drive it.
+#[derive(Clone, Default)]
+pub struct ManualClock {
+    time: Rc<Cell<Duration>>,
+}
+
+impl ManualClock {
+    pub fn new() -> ManualClock {
+        ManualClock::default()
+    }
+
+    /// Jump to `time`. Jumping back is allowed, the `FrameClock` then sees a frame of length 0.
+    pub fn set(&self, time: Duration) {
+        self.time.set(time);
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.time.set(self.time.get() + duration);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&mut self) -> Duration {
+        self.time.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_step_clock_is_exact() {
+        let mut clock = FixedStepClock::new(60);
+        let times: Vec<_> = (0..=120).map(|_| clock.now()).collect();
+        assert_eq!(times[0], Duration::ZERO);
+        assert_eq!(times[1], Duration::from_nanos(16_666_666));
+        // Frame 120 at 60 Hz gives 2 seconds
+        assert_eq!(times[120], Duration::from_secs(2));
+
+        assert_eq!(FixedStepClock::starting_at_frame(60, 120).now(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn manual_clock_clones_share_the_time() {
+        let driver = ManualClock::new();
+        let mut clock = driver.clone();
+        assert_eq!(clock.now(), Duration::ZERO);
+        // Reading the time doesn't move it
+        assert_eq!(clock.now(), Duration::ZERO);
+
+        driver.advance(Duration::from_millis(250));
+        assert_eq!(clock.now(), Duration::from_millis(250));
+        driver.set(Duration::from_secs(5));
+        assert_eq!(clock.now(), Duration::from_secs(5));
+        driver.set(Duration::from_secs(1));
+        assert_eq!(clock.now(), Duration::from_secs(1));
+    }
+}
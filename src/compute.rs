@@ -0,0 +1,213 @@
+/*
+Compute shaders, for running general purpose work on the GPU (OpenGL 4.3 and above).
+
+A compute program is a single compute shader, which runs in a grid of work groups instead of on
+vertices or fragments. It reads and writes its data through shader storage buffer objects (SSBOs):
+plain buffers bound to numbered binding points, which the shader declares as
+
+    layout (std430, binding = 0) buffer Particles { Particle particles[]; };
+
+The GPU runs the dispatched work asynchronously, and its writes are not visible to the rest of the
+pipeline until a memory barrier says so, which is why `dispatch` always takes the barriers to
+insert after the work.
+ */
+
+use std::ffi::c_void;
+use std::marker::PhantomData;
+use std::mem::size_of;
+use bytemuck::Pod;
+use crate::shader::{ShaderError, ShaderProgram, ShaderSource, ShaderStage};
+use crate::vertex_buffer::{enable_vertex_attributes, Vertex};
+
+/// A linked compute program, deleted when dropped.
+pub struct ComputeProgram {
+    program: ShaderProgram,
+}
+
+impl ComputeProgram {
+    /// Compile and link a compute shader.
+    ///
+    /// # Arguments
+    /// * `name` - Name of the source for error messages, usually its file name
+    /// * `code` - The GLSL code of the compute shader
+    pub fn new(name: &str, code: &str) -> Result<ComputeProgram, ShaderError> {
+        Ok(ComputeProgram {
+            program: ShaderProgram::new(&[ShaderSource::new(name, code, ShaderStage::Compute)])?,
+        })
+    }
+
+    /// Compile and link a compute shader of the library, which is reloaded when it changes in
+    /// debug builds.
+    ///
+    /// # Arguments
+    /// * `name` - Name of the shader in the library, such as "particles/update"
+    pub fn from_library(name: &str) -> Result<ComputeProgram, ShaderError> {
+        Ok(ComputeProgram {
+            program: ShaderProgram::from_library(&[name])?,
+        })
+    }
+
+    /// The underlying program, to set uniforms on.
+    pub fn program(&self) -> &ShaderProgram {
+        &self.program
+    }
+
+    /// Number of invocations in a work group, as declared by the shader's `local_size_*`.
+    pub fn work_group_size(&self) -> [u32; 3] {
+        let mut work_group_size = [0i32; 3];
+        unsafe {
+            gl::GetProgramiv(self.program.id(), gl::COMPUTE_WORK_GROUP_SIZE, work_group_size.as_mut_ptr());
+        }
+        work_group_size.map(|size| size as u32)
+    }
+
+    /// Run the program over a grid of work groups, then insert memory barriers so the following
+    /// commands see what it wrote.
+    ///
+    /// # Arguments
+    /// * `work_groups` - Number of work groups in each dimension, see `work_groups_for`
+    /// * `barriers` - What the written data is used for next, such as
+    ///   gl::SHADER_STORAGE_BARRIER_BIT for another dispatch reading the same buffer, or
+    ///   gl::VERTEX_ATTRIB_ARRAY_BARRIER_BIT for drawing from it. Reading the results back with
+    ///   `StorageBuffer::read` needs gl::BUFFER_UPDATE_BARRIER_BIT
+    pub fn dispatch(&self, work_groups: [u32; 3], barriers: u32) {
+        self.program.use_program();
+        unsafe {
+            gl::DispatchCompute(work_groups[0], work_groups[1], work_groups[2]);
+            gl::MemoryBarrier(barriers);
+        }
+    }
+
+    /// The number of work groups along x needed to cover `invocations` invocations. The shader
+    /// must ignore the extra invocations of the last group.
+    pub fn work_groups_for(&self, invocations: usize) -> [u32; 3] {
+        let work_group_size = self.work_group_size()[0] as usize;
+        [invocations.div_ceil(work_group_size) as u32, 1, 1]
+    }
+}
+
+/// A shader storage buffer holding an array of `T`, deleted when dropped.
+///
+/// `T` must have the same layout in Rust as in the shader's std430 block: a `#[repr(C)]` struct
+/// whose vec3 and vec4 fields are aligned to 16 bytes, and vec2 fields to 8 bytes.
+pub struct StorageBuffer<T: Pod> {
+    buffer: u32,
+    len: usize,
+    element_type: PhantomData<T>,
+}
+
+impl<T: Pod> StorageBuffer<T> {
+    /// Create a buffer holding a copy of `data`.
+    ///
+    /// # Arguments
+    /// * `data` - The initial content, its length is the length of the buffer
+    /// * `usage` - How the buffer will be used, such as gl::DYNAMIC_COPY for data that is
+    ///   written by a shader and read by another one
+    pub fn new(data: &[T], usage: u32) -> StorageBuffer<T> {
+        let mut buffer = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut buffer);
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, buffer);
+            gl::BufferData(gl::SHADER_STORAGE_BUFFER, size_of_val(data) as isize,
+                           data.as_ptr() as *const c_void, usage);
+        }
+
+        StorageBuffer {
+            buffer,
+            len: data.len(),
+            element_type: PhantomData,
+        }
+    }
+
+    /// Bind the buffer to the `binding` of a shader's storage block.
+    pub fn bind(&self, binding: u32) {
+        unsafe { gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, binding, self.buffer); }
+    }
+
+    /// Replace the content of the buffer, which must have the same length.
+    pub fn write(&self, data: &[T]) {
+        assert_eq!(data.len(), self.len, "The storage buffer holds {} elements", self.len);
+        unsafe {
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, self.buffer);
+            gl::BufferSubData(gl::SHADER_STORAGE_BUFFER, 0, size_of_val(data) as isize,
+                              data.as_ptr() as *const c_void);
+        }
+    }
+
+    /// Copy the content of the buffer back to the CPU. Waits for the GPU to finish writing it,
+    /// the writes of compute shaders must be followed by gl::BUFFER_UPDATE_BARRIER_BIT.
+    pub fn read(&self) -> Vec<T> {
+        let mut data = vec![T::zeroed(); self.len];
+        unsafe {
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, self.buffer);
+            gl::GetBufferSubData(gl::SHADER_STORAGE_BUFFER, 0, (self.len * size_of::<T>()) as isize,
+                                 data.as_mut_ptr() as *mut c_void);
+        }
+        data
+    }
+
+    /// The GL handle of the buffer, for instance to also bind it as a vertex buffer.
+    pub fn id(&self) -> u32 {
+        self.buffer
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<T: Vertex> StorageBuffer<T> {
+    /// Set up and enable the attributes of `T`, reading from this buffer, in the vertex array
+    /// object currently bound, so what the compute shaders wrote is drawn as it is.
+    pub fn enable_attributes(&self) {
+        unsafe { gl::BindBuffer(gl::ARRAY_BUFFER, self.buffer); }
+        enable_vertex_attributes::<T>();
+    }
+}
+
+impl<T: Pod> Drop for StorageBuffer<T> {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteBuffers(1, &self.buffer); }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use crate::context_config::ContextConfig;
+    use crate::headless::HeadlessContext;
+    use super::*;
+
+    const SQUARE_COMPUTE: &str = "#version 430 core
+layout (local_size_x = 32) in;
+layout (std430, binding = 0) buffer Values { uint values[]; };
+
+void main() {
+    uint index = gl_GlobalInvocationID.x;
+    if (index < values.length()) {
+        values[index] *= values[index];
+    }
+}
+";
+
+    #[test]
+    fn dispatch_writes_storage_buffers() {
+        let _context = HeadlessContext::new(&ContextConfig::default(), 1, 1).unwrap();
+
+        let program = ComputeProgram::new("square.comp", SQUARE_COMPUTE).unwrap();
+        assert_eq!(program.work_group_size(), [32, 1, 1]);
+
+        // Not a multiple of the work group size, so the last group is only partly used
+        let values: Vec<u32> = (0..100).collect();
+        let buffer = StorageBuffer::new(&values, gl::DYNAMIC_COPY);
+        buffer.bind(0);
+        assert_eq!(program.work_groups_for(buffer.len()), [4, 1, 1]);
+        program.dispatch(program.work_groups_for(buffer.len()), gl::BUFFER_UPDATE_BARRIER_BIT);
+
+        let squares: Vec<u32> = values.iter().map(|value| value * value).collect();
+        assert_eq!(buffer.read(), squares);
+    }
+}
@@ -0,0 +1,160 @@
+/*
+Particles simulated entirely on the GPU with a compute shader (needs OpenGL 4.3).
+
+The particles live in a shader storage buffer: every fixed update, the compute shader moves them
+under gravity and bounces them off the edges of the screen, then the very same buffer is bound as a
+vertex buffer and drawn as points. The particles never go back to the CPU.
+ */
+
+use bytemuck::{Pod, Zeroable};
+use crate::compute::{ComputeProgram, StorageBuffer};
+use crate::frame_clock::FrameTime;
+use crate::scene::Scene;
+use crate::shader::{ShaderError, ShaderProgram};
+use crate::vertex_layout;
+
+pub const PARTICLE_COUNT: usize = 4096;
+
+/// A particle as laid out in the storage buffer, matching the `Particle` struct of the shader.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Pod, Zeroable)]
+pub struct Particle {
+    pub position: [f32; 2],
+    pub velocity: [f32; 2],
+}
+
+// The same struct is also read as vertices by particle.vert
+vertex_layout!(Particle { position: 0, velocity: 1 });
+
+/// A spiral of particles, all spinning around its center.
+pub fn initial_particles() -> Vec<Particle> {
+    // The golden angle spreads the particles evenly over the disc
+    const GOLDEN_ANGLE: f32 = 2.399_963;
+
+    (0..PARTICLE_COUNT).map(|index| {
+        let angle = index as f32 * GOLDEN_ANGLE;
+        let radius = 0.6 * ((index as f32 + 0.5) / PARTICLE_COUNT as f32).sqrt();
+        Particle {
+            position: [radius * angle.cos(), 0.2 + radius * angle.sin()],
+            velocity: [-0.5 * angle.sin(), 0.5 * angle.cos()],
+        }
+    }).collect()
+}
+
+#[derive(Default)]
+pub struct Particles {
+    vertex_array_object: u32,
+    particles: Option<StorageBuffer<Particle>>,
+    compute_program: Option<ComputeProgram>,
+    shader_program: Option<ShaderProgram>,
+}
+
+impl Scene for Particles {
+    fn setup(&mut self) -> Result<(), ShaderError> {
+        self.compute_program = Some(ComputeProgram::from_library("particles/update")?);
+
+        self.shader_program = Some(ShaderProgram::from_library(&["particles/point", "color/interpolated"])?);
+
+        unsafe {
+            gl::GenVertexArrays(1, &mut self.vertex_array_object);
+            gl::BindVertexArray(self.vertex_array_object);
+        }
+        // A storage buffer is a buffer like any other, so it can also be the source of the vertices
+        let particles = StorageBuffer::new(&initial_particles(), gl::DYNAMIC_COPY);
+        particles.enable_attributes();
+        self.particles = Some(particles);
+
+        // Let the vertex shader choose the size of the points
+        unsafe { gl::Enable(gl::PROGRAM_POINT_SIZE); }
+        Ok(())
+    }
+
+    fn frame(&mut self, time: &FrameTime) {
+        let compute_program = self.compute_program.as_ref().unwrap();
+        let particles = self.particles.as_ref().unwrap();
+
+        compute_program.program().use_program();
+        compute_program.program().set_uniform("timestep", time.fixed_timestep.as_secs_f32());
+        particles.bind(0);
+        for _ in 0..time.fixed_steps {
+            // Each update reads what the previous one wrote, and the last one is drawn from
+            compute_program.dispatch(compute_program.work_groups_for(particles.len()),
+                                     gl::SHADER_STORAGE_BARRIER_BIT | gl::VERTEX_ATTRIB_ARRAY_BARRIER_BIT);
+        }
+
+        unsafe {
+            gl::ClearColor(0.1, 0.1, 0.1, 1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+
+            self.shader_program.as_ref().unwrap().use_program();
+            gl::BindVertexArray(self.vertex_array_object);
+            gl::DrawArrays(gl::POINTS, 0, particles.len() as i32);
+        }
+    }
+
+    fn teardown(&mut self) {
+        unsafe { gl::Disable(gl::PROGRAM_POINT_SIZE); }
+        self.shader_program = None;
+        self.compute_program = None;
+        unsafe { gl::DeleteVertexArrays(1, &self.vertex_array_object); }
+        self.particles = None;
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use crate::compute::{ComputeProgram, StorageBuffer};
+    use crate::context_config::ContextConfig;
+    use crate::headless::HeadlessContext;
+    use super::{initial_particles, Particle};
+
+    const TIMESTEP: f32 = 1.0 / 60.0;
+
+    /// The same update as particles.comp
+    fn update_particle(particle: &mut Particle) {
+        particle.velocity[1] -= 0.98 * TIMESTEP;
+        for axis in 0..2 {
+            particle.position[axis] += particle.velocity[axis] * TIMESTEP;
+            if particle.position[axis].abs() > 1.0 {
+                particle.position[axis] = particle.position[axis].signum();
+                particle.velocity[axis] = -particle.velocity[axis] * 0.8;
+            }
+        }
+    }
+
+    #[test]
+    fn gpu_updates_match_the_cpu() {
+        let _context = HeadlessContext::new(&ContextConfig::default(), 1, 1).unwrap();
+
+        // Also a few particles that are about to bounce off every edge
+        let mut expected = initial_particles();
+        expected.extend([
+            Particle { position: [0.0, -0.99], velocity: [0.0, -1.0] },
+            Particle { position: [0.0, 0.99], velocity: [0.0, 1.5] },
+            Particle { position: [-0.99, 0.0], velocity: [-1.0, 0.0] },
+            Particle { position: [0.99, 0.0], velocity: [1.0, 0.0] },
+        ]);
+        let particles = StorageBuffer::new(&expected, gl::DYNAMIC_COPY);
+
+        let compute_program = ComputeProgram::from_library("particles/update").unwrap();
+        compute_program.program().use_program();
+        compute_program.program().set_uniform("timestep", TIMESTEP);
+        particles.bind(0);
+
+        for _ in 0..60 {
+            compute_program.dispatch(compute_program.work_groups_for(particles.len()),
+                                     gl::SHADER_STORAGE_BARRIER_BIT | gl::BUFFER_UPDATE_BARRIER_BIT);
+            expected.iter_mut().for_each(update_particle);
+        }
+
+        let actual = particles.read();
+        for (index, (actual, expected)) in actual.iter().zip(&expected).enumerate() {
+            let values = actual.position.iter().chain(&actual.velocity)
+                .zip(expected.position.iter().chain(&expected.velocity));
+            for (actual_value, expected_value) in values {
+                assert!((actual_value - expected_value).abs() < 1e-4,
+                        "Particle {} is {:?} on the GPU, but {:?} on the CPU", index, actual, expected);
+            }
+        }
+    }
+}
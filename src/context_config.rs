@@ -0,0 +1,189 @@
+/*
+Configuration of the OpenGL context to create.
+
+Without it we get whatever context the driver picks by default, which is usually not the
+"330 core" our shaders are written for. The defaults match the shaders: OpenGL 3.3, core profile.
+ */
+
+use std::ffi::CStr;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Profile {
+    /// Only the modern API, deprecated functions (such as drawing without a VAO) are removed.
+    Core,
+    /// The modern API together with all the deprecated functions.
+    Compatibility,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SwapInterval {
+    /// Swap the buffers immediately, frames may tear.
+    Immediate,
+    /// Wait for the vertical blank before swapping.
+    VSync,
+    /// Wait for the vertical blank, unless the frame is late, in which case swap immediately.
+    Adaptive,
+}
+
+#[derive(Clone, Debug)]
+pub struct ContextConfig {
+    pub major_version: u8,
+    pub minor_version: u8,
+    pub profile: Profile,
+    /// Remove the deprecated functions even from the compatibility profile, required on macOS.
+    pub forward_compatible: bool,
+    /// Ask the driver for a debug context, which reports much more errors and warnings.
+    pub debug: bool,
+    pub depth_bits: u8,
+    pub stencil_bits: u8,
+    /// Number of samples per pixel for multisample anti-aliasing, 0 turns MSAA off.
+    pub msaa_samples: u8,
+    pub swap_interval: SwapInterval,
+}
+
+impl Default for ContextConfig {
+    fn default() -> Self {
+        ContextConfig {
+            major_version: 3,
+            minor_version: 3,
+            profile: Profile::Core,
+            forward_compatible: false,
+            debug: false,
+            depth_bits: 24,
+            stencil_bits: 8,
+            msaa_samples: 0,
+            swap_interval: SwapInterval::VSync,
+        }
+    }
+}
+
+impl ContextConfig {
+    /// Parse a version such as "3.3" into its major and minor parts.
+    pub fn parse_version(version: &str) -> Result<(u8, u8), String> {
+        let invalid = || format!("Invalid OpenGL version '{}', expected <major>.<minor>", version);
+        let (major, minor) = version.split_once('.').ok_or_else(invalid)?;
+        Ok((major.parse().map_err(|_| invalid())?, minor.parse().map_err(|_| invalid())?))
+    }
+
+    /// Make sure the current context is the one that was asked for. Drivers are allowed to
+    /// hand out a newer version than requested, but never an older one or another profile.
+    ///
+    /// Must be called after the GL functions were loaded.
+    pub fn check_current_context(&self) -> Result<(), String> {
+        let (major_version, minor_version) = current_version()
+            .ok_or_else(|| format!("Could not read the version of the OpenGL context ({})", current_renderer()))?;
+
+        let requested = (self.major_version as i32, self.minor_version as i32);
+        if (major_version, minor_version) < requested {
+            return Err(format!("Requested an OpenGL {} context, but the driver created a {}.{} one ({})",
+                               self, major_version, minor_version, current_renderer()));
+        }
+
+        // Profiles only exist since OpenGL 3.2, and so does the query of the profile
+        let mut profile_mask = 0;
+        if (major_version, minor_version) >= (3, 2) {
+            unsafe { gl::GetIntegerv(gl::CONTEXT_PROFILE_MASK, &mut profile_mask); }
+        }
+        if requested >= (3, 2) && self.profile == Profile::Core
+            && profile_mask as u32 & gl::CONTEXT_CORE_PROFILE_BIT == 0 {
+            return Err(format!("Requested an OpenGL {} context, but the driver created a \
+                                compatibility one ({})", self, current_renderer()));
+        }
+
+        Ok(())
+    }
+}
+
+/// The version of the current context, from the start of the version string such as
+/// "2.1 Mesa 24.0.5". Unlike gl::MAJOR_VERSION, which only exists since OpenGL 3.0, the string
+/// can be read from any context.
+pub fn current_version() -> Option<(i32, i32)> {
+    let version = unsafe { gl::GetString(gl::VERSION) };
+    if version.is_null() {
+        return None;
+    }
+    let version = unsafe { CStr::from_ptr(version as *const _) }.to_string_lossy();
+    parse_version_string(&version)
+}
+
+/// The major and minor version at the start of a version string, such as (4, 5) for
+/// "4.5 (Core Profile) Mesa 24.0.5".
+fn parse_version_string(version: &str) -> Option<(i32, i32)> {
+    let number = version.split(' ').next()?;
+    let mut parts = number.split('.');
+    Some((parts.next()?.parse().ok()?, parts.next()?.parse().ok()?))
+}
+
+/// "<renderer> - <version>" of the current context, such as "llvmpipe - 4.5 (Core Profile) Mesa".
+pub fn current_renderer() -> String {
+    let get_string = |name| unsafe {
+        let string = gl::GetString(name);
+        if string.is_null() {
+            return String::from("unknown");
+        }
+        CStr::from_ptr(string as *const _).to_string_lossy().into_owned()
+    };
+    format!("{} - {}", get_string(gl::RENDERER), get_string(gl::VERSION))
+}
+
+impl fmt::Display for ContextConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{} {}", self.major_version, self.minor_version, self.profile)?;
+        if self.forward_compatible {
+            write!(f, " forward compatible")?;
+        }
+        if self.debug {
+            write!(f, " debug")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Profile::Core => write!(f, "core"),
+            Profile::Compatibility => write!(f, "compatibility"),
+        }
+    }
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(profile: &str) -> Result<Self, Self::Err> {
+        match profile {
+            "core" => Ok(Profile::Core),
+            "compatibility" | "compat" => Ok(Profile::Compatibility),
+            _ => Err(format!("Invalid profile '{}', expected core or compatibility", profile)),
+        }
+    }
+}
+
+impl FromStr for SwapInterval {
+    type Err = String;
+
+    fn from_str(swap_interval: &str) -> Result<Self, Self::Err> {
+        match swap_interval {
+            "off" => Ok(SwapInterval::Immediate),
+            "on" => Ok(SwapInterval::VSync),
+            "adaptive" => Ok(SwapInterval::Adaptive),
+            _ => Err(format!("Invalid vsync mode '{}', expected on, off or adaptive", swap_interval)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_are_read_from_the_version_string() {
+        assert_eq!(parse_version_string("2.1 Mesa 24.0.5"), Some((2, 1)));
+        assert_eq!(parse_version_string("4.5 (Core Profile) Mesa 24.0.5"), Some((4, 5)));
+        assert_eq!(parse_version_string("4.6.0 NVIDIA 535.183.01"), Some((4, 6)));
+        assert_eq!(parse_version_string("OpenGL ES 3.2"), None);
+    }
+}
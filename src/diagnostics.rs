@@ -0,0 +1,315 @@
+/*
+Structured shader compilation diagnostics.
+
+Drivers report compilation errors as a single log, in a format of their own:
+
+    0:11(2): error: `nope' undeclared                  (Mesa)
+    0(11) : error C1008: undefined variable "nope"     (NVIDIA)
+    ERROR: 0:11: 'nope' : undeclared identifier        (AMD, Intel on Windows, ANGLE)
+
+The number before the line is the source string of the `#line` directives (see preprocessor.rs),
+or the file name itself between double quotes when the driver has GL_ARB_shading_language_include.
+
+`parse_log` turns such a log into one `Diagnostic` per message, with the file it refers to and the
+offending line of code, which display like rustc's errors:
+
+    error: `nope' undeclared
+      --> common/lighting.glsl:11:2
+       |
+    11 |   nope;
+       |  ^
+ */
+
+use std::fmt;
+
+/// One of the files a shader was made of, see `PreprocessedSource::sources`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceString {
+    /// The name of the shader itself, or the path of an included file.
+    pub name: String,
+    pub code: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Severity {
+    Error,
+    Warning,
+    /// Anything else the driver had to say.
+    Note,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+/// A message of the driver's log, located in the file it refers to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// Name of the file, as in `SourceString::name`.
+    pub file: String,
+    /// Starting at 1. None when the message is about the whole file.
+    pub line: Option<u32>,
+    /// Starting at 1, only some drivers report it.
+    pub column: Option<u32>,
+    /// The code of the line, when it could be found.
+    pub source_line: Option<String>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}: {}", self.severity, self.message)?;
+
+        let Some(line) = self.line else {
+            return write!(f, "  --> {}", self.file);
+        };
+        // Wide enough for the line number
+        let gutter = " ".repeat(line.to_string().len());
+        write!(f, "{}--> {}:{}", gutter, self.file, line)?;
+        if let Some(column) = self.column {
+            write!(f, ":{}", column)?;
+        }
+
+        if let Some(source_line) = &self.source_line {
+            write!(f, "\n{} |\n{} | {}", gutter, line, source_line)?;
+            if let Some(column) = self.column {
+                // Keep the tabs, so the caret lines up with the code above it
+                let indentation: String = source_line.chars()
+                    .take(column.saturating_sub(1) as usize)
+                    .map(|character| if character == '\t' { '\t' } else { ' ' })
+                    .collect();
+                write!(f, "\n{} | {}^", gutter, indentation)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Split a driver's compilation log into diagnostics.
+///
+/// Lines in none of the known formats continue the message of the previous diagnostic, or become a
+/// diagnostic of their own about the shader as a whole.
+///
+/// # Arguments
+/// * `log` - The compilation log of a shader
+/// * `sources` - The files of the shader, in the order of the source strings of its `#line`
+///   directives. The first one is the shader itself
+pub fn parse_log(log: &str, sources: &[SourceString]) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    for log_line in log.lines().map(str::trim_end).filter(|log_line| !log_line.is_empty()) {
+        let Some(location) = parse_mesa(log_line).or_else(|| parse_nvidia(log_line)).or_else(|| parse_amd(log_line)) else {
+            match diagnostics.last_mut() {
+                Some(diagnostic) => {
+                    diagnostic.message.push('\n');
+                    diagnostic.message.push_str(log_line.trim());
+                }
+                None => diagnostics.push(Diagnostic {
+                    severity: Severity::Error,
+                    message: String::from(log_line.trim()),
+                    file: sources.first().map(|source| source.name.clone()).unwrap_or_default(),
+                    line: None,
+                    column: None,
+                    source_line: None,
+                }),
+            }
+            continue;
+        };
+
+        let source = match location.file {
+            FileReference::Number(number) => sources.get(number as usize),
+            FileReference::Name(name) => sources.iter().find(|source| source.name == name),
+        };
+        let file = match (source, location.file) {
+            (Some(source), _) => source.name.clone(),
+            (None, FileReference::Number(number)) => format!("source string {}", number),
+            (None, FileReference::Name(name)) => String::from(name),
+        };
+        // Drivers use line 0 for messages about the whole file
+        let line = Some(location.line).filter(|line| *line > 0);
+        let source_line = source.zip(line)
+            .and_then(|(source, line)| source.code.lines().nth(line as usize - 1))
+            .map(String::from);
+
+        diagnostics.push(Diagnostic {
+            severity: location.severity,
+            message: String::from(location.message),
+            file,
+            line,
+            column: location.column.filter(|column| *column > 0 && line.is_some()),
+            source_line,
+        });
+    }
+    diagnostics
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FileReference<'a> {
+    Number(u32),
+    Name(&'a str),
+}
+
+/// What a line of the log says, before the file it refers to is resolved.
+struct Location<'a> {
+    file: FileReference<'a>,
+    line: u32,
+    column: Option<u32>,
+    severity: Severity,
+    message: &'a str,
+}
+
+/// `0:11(2): error: message`, or `"file.glsl":11(2): error: message`.
+fn parse_mesa(log_line: &str) -> Option<Location<'_>> {
+    let (file, rest) = parse_file_reference(log_line)?;
+    let (line, rest) = parse_number(rest.strip_prefix(':')?)?;
+    let (column, rest) = match rest.strip_prefix('(') {
+        Some(rest) => {
+            let (column, rest) = parse_number(rest)?;
+            (Some(column), rest.strip_prefix(')')?)
+        }
+        None => (None, rest),
+    };
+    let (severity, message) = rest.strip_prefix(':')?.trim_start().split_once(':')?;
+
+    Some(Location { file, line, column, severity: parse_severity(severity)?, message: message.trim() })
+}
+
+/// `0(11) : error C1008: message`, or `"file.glsl"(11) : error C1008: message`.
+fn parse_nvidia(log_line: &str) -> Option<Location<'_>> {
+    let (file, rest) = parse_file_reference(log_line)?;
+    let (line, rest) = parse_number(rest.strip_prefix('(')?)?;
+    let (severity_and_code, message) = rest.strip_prefix(')')?.trim_start().strip_prefix(':')?.split_once(':')?;
+    let severity = severity_and_code.split_whitespace().next()?;
+
+    Some(Location { file, line, column: None, severity: parse_severity(severity)?, message: message.trim() })
+}
+
+/// `ERROR: 0:11: message`, or `WARNING: 0:11: message`.
+fn parse_amd(log_line: &str) -> Option<Location<'_>> {
+    let (severity, rest) = log_line.split_once(':')?;
+    let severity = parse_severity(severity)?;
+    let (file, rest) = parse_file_reference(rest.trim_start())?;
+    let (line, rest) = parse_number(rest.strip_prefix(':')?)?;
+
+    Some(Location { file, line, column: None, severity, message: rest.strip_prefix(':')?.trim() })
+}
+
+/// A source string number, or a file name between double quotes, at the start of `text`.
+fn parse_file_reference(text: &str) -> Option<(FileReference<'_>, &str)> {
+    match text.strip_prefix('"') {
+        Some(quoted) => {
+            let (name, rest) = quoted.split_once('"')?;
+            Some((FileReference::Name(name), rest))
+        }
+        None => {
+            let (number, rest) = parse_number(text)?;
+            Some((FileReference::Number(number), rest))
+        }
+    }
+}
+
+fn parse_number(text: &str) -> Option<(u32, &str)> {
+    let digits = text.find(|character: char| !character.is_ascii_digit()).unwrap_or(text.len());
+    Some((text[..digits].parse().ok()?, &text[digits..]))
+}
+
+fn parse_severity(severity: &str) -> Option<Severity> {
+    // Also "preprocessor error", "fatal error", etc...
+    let severity = severity.trim().to_ascii_lowercase();
+    if severity.ends_with("error") {
+        Some(Severity::Error)
+    } else if severity.ends_with("warning") {
+        Some(Severity::Warning)
+    } else if severity == "info" || severity == "note" {
+        Some(Severity::Note)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sources() -> Vec<SourceString> {
+        vec![
+            SourceString { name: String::from("main.frag"), code: String::from("#version 330 core\nvoid main() {\n\tnope;\n}\n") },
+            SourceString { name: String::from("common/color.glsl"), code: String::from("vec4 color() {\n    return nah;\n}\n") },
+        ]
+    }
+
+    #[test]
+    fn mesa_logs_are_parsed() {
+        let log = "0:3(2): error: `nope' undeclared\n\"common/color.glsl\":2(12): warning: `nah' used\n1:2(5): error: oops";
+        let diagnostics = parse_log(log, &sources());
+
+        assert_eq!(diagnostics, [
+            Diagnostic {
+                severity: Severity::Error,
+                message: String::from("`nope' undeclared"),
+                file: String::from("main.frag"),
+                line: Some(3),
+                column: Some(2),
+                source_line: Some(String::from("\tnope;")),
+            },
+            Diagnostic {
+                severity: Severity::Warning,
+                message: String::from("`nah' used"),
+                file: String::from("common/color.glsl"),
+                line: Some(2),
+                column: Some(12),
+                source_line: Some(String::from("    return nah;")),
+            },
+            Diagnostic {
+                severity: Severity::Error,
+                message: String::from("oops"),
+                file: String::from("common/color.glsl"),
+                line: Some(2),
+                column: Some(5),
+                source_line: Some(String::from("    return nah;")),
+            },
+        ]);
+    }
+
+    #[test]
+    fn nvidia_and_amd_logs_are_parsed() {
+        let log = "1(2) : error C1008: undefined variable \"nah\"\nERROR: 0:3: 'nope' : undeclared identifier\n\
+                   ERROR: 2 compilation errors.  No code generated.";
+        let diagnostics = parse_log(log, &sources());
+
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!((diagnostics[0].severity, diagnostics[0].file.as_str(), diagnostics[0].line),
+                   (Severity::Error, "common/color.glsl", Some(2)));
+        assert_eq!(diagnostics[0].message, "undefined variable \"nah\"");
+        assert_eq!((diagnostics[1].file.as_str(), diagnostics[1].line), ("main.frag", Some(3)));
+        // The summary line has no location, so it ends up with the previous message
+        assert_eq!(diagnostics[1].message, "'nope' : undeclared identifier\nERROR: 2 compilation errors.  No code generated.");
+    }
+
+    #[test]
+    fn unknown_formats_are_kept_whole() {
+        let diagnostics = parse_log("Something went wrong\nin the compiler", &sources());
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "Something went wrong\nin the compiler");
+        assert_eq!((diagnostics[0].file.as_str(), diagnostics[0].line), ("main.frag", None));
+    }
+
+    #[test]
+    fn diagnostics_display_like_rustc() {
+        let diagnostics = parse_log("0:3(2): error: `nope' undeclared\n7:0(0): warning: unknown source", &sources());
+
+        assert_eq!(diagnostics[0].to_string(), "error: `nope' undeclared\n \
+            --> main.frag:3:2\n  \
+            |\n\
+            3 | \tnope;\n  \
+            | \t^");
+        assert_eq!(diagnostics[1].to_string(), "warning: unknown source\n  --> source string 7");
+    }
+}
@@ -0,0 +1,83 @@
+/*
+A registry of every exercise in this repo.
+
+Each exercise is identified by "<chapter>/<name>" (for example "chapter5/exercise3"), so the
+binary can pick the one to run from the command line instead of hardcoding it in main.rs.
+ */
+
+use crate::scene::Scene;
+use crate::hello_triangle::HelloTriangle;
+use crate::chapter5_exercises::exercise1::Exercise1;
+use crate::chapter5_exercises::exercise2::Exercise2;
+use crate::chapter5_exercises::exercise3::Exercise3;
+use crate::chapter6_exercises::glowing_green_triangle::GlowingGreenTriangle;
+use crate::chapter6_exercises::more_attributes::MoreAttributes;
+use crate::chapter30_exercises::houses::Houses;
+use crate::compute_exercises::particles::Particles;
+
+/// The exercise that runs when none was picked on the command line.
+pub const DEFAULT_EXERCISE: &str = "chapter6/more_attributes";
+
+pub struct Exercise {
+    /// Name of the exercise, unique within its chapter.
+    pub name: &'static str,
+    /// Chapter of the book the exercise belongs to, such as "chapter5".
+    pub chapter: &'static str,
+    /// Create the exercise's scene. The GL objects are only created once `setup` is called.
+    pub create: fn() -> Box<dyn Scene>,
+}
+
+impl Exercise {
+    /// The id used to pick this exercise from the command line, such as "chapter5/exercise3".
+    pub fn id(&self) -> String {
+        format!("{}/{}", self.chapter, self.name)
+    }
+}
+
+pub const EXERCISES: &[Exercise] = &[
+    Exercise {
+        name: "hello_triangle",
+        chapter: "chapter5",
+        create: || Box::<HelloTriangle>::default(),
+    },
+    Exercise {
+        name: "exercise1",
+        chapter: "chapter5",
+        create: || Box::<Exercise1>::default(),
+    },
+    Exercise {
+        name: "exercise2",
+        chapter: "chapter5",
+        create: || Box::<Exercise2>::default(),
+    },
+    Exercise {
+        name: "exercise3",
+        chapter: "chapter5",
+        create: || Box::<Exercise3>::default(),
+    },
+    Exercise {
+        name: "glowing_green_triangle",
+        chapter: "chapter6",
+        create: || Box::<GlowingGreenTriangle>::default(),
+    },
+    Exercise {
+        name: "more_attributes",
+        chapter: "chapter6",
+        create: || Box::<MoreAttributes>::default(),
+    },
+    Exercise {
+        name: "houses",
+        chapter: "chapter30",
+        create: || Box::<Houses>::default(),
+    },
+    Exercise {
+        name: "particles",
+        chapter: "compute",
+        create: || Box::<Particles>::default(),
+    },
+];
+
+/// Find the position of an exercise in `EXERCISES` by its id, such as "chapter5/exercise3".
+pub fn find_exercise_index(id: &str) -> Option<usize> {
+    EXERCISES.iter().position(|exercise| exercise.id() == id)
+}
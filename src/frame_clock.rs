@@ -0,0 +1,287 @@
+/*
+Frame timing for the host loop.
+
+The host ticks a single `FrameClock` once per frame and passes the resulting `FrameTime` to the
+active scene, so every scene sees the same time for the whole frame instead of reading the
+system time on its own.
+
+Where the time comes from is up to the `Clock` the frame clock is created with, see clock.rs.
+
+The clock also runs a fixed timestep accumulator: simulations that need a stable step (physics,
+particles, etc...) run `fixed_steps` updates of `fixed_timestep` each frame, no matter how fast
+frames are actually rendered.
+ */
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::Duration;
+use crate::clock::{Clock, RealClock};
+
+/// The default fixed timestep - 60 updates per second.
+pub const DEFAULT_FIXED_TIMESTEP: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// Number of frames the rolling statistics are computed over.
+const STATISTICS_WINDOW: usize = 120;
+
+/// Upper bound on the fixed updates of a single frame, so a long stall (a breakpoint, dragging
+/// the window, etc...) does not make the simulation spend seconds catching up.
+const MAX_FIXED_STEPS_PER_FRAME: u32 = 8;
+
+/// Timing information of a single frame.
+#[derive(Clone, Copy, Debug)]
+pub struct FrameTime {
+    /// Time since the clock started, at the start of this frame.
+    pub elapsed: Duration,
+    /// Time since the start of the previous frame. Zero on the first frame.
+    pub delta: Duration,
+    /// Index of this frame, starting at 0.
+    pub frame_index: u64,
+    /// Length of a single fixed update.
+    pub fixed_timestep: Duration,
+    /// Number of fixed updates to run this frame.
+    pub fixed_steps: u32,
+    /// How far (between 0 and 1) the frame is between the last fixed update and the next one,
+    /// to interpolate the rendered state with.
+    pub fixed_step_alpha: f32,
+}
+
+pub struct FrameClock {
+    clock: Box<dyn Clock>,
+    last_tick: Option<Duration>,
+    frame_index: u64,
+    fixed_timestep: Duration,
+    accumulator: Duration,
+    statistics: FrameStatistics,
+}
+
+impl FrameClock {
+    /// Create a frame clock that follows the wall clock, starting now.
+    ///
+    /// # Arguments
+    /// * `fixed_timestep` - Length of a single fixed update, see `DEFAULT_FIXED_TIMESTEP`
+    pub fn new(fixed_timestep: Duration) -> FrameClock {
+        FrameClock::with_clock(Box::new(RealClock::new()), fixed_timestep)
+    }
+
+    /// Create a frame clock that reads the time from `clock`.
+    ///
+    /// # Arguments
+    /// * `clock` - Source of the time, such as a `FixedStepClock` for reproducible frames
+    /// * `fixed_timestep` - Length of a single fixed update, see `DEFAULT_FIXED_TIMESTEP`
+    pub fn with_clock(clock: Box<dyn Clock>, fixed_timestep: Duration) -> FrameClock {
+        assert!(!fixed_timestep.is_zero(), "The fixed timestep must be positive");
+        FrameClock {
+            clock,
+            last_tick: None,
+            frame_index: 0,
+            fixed_timestep,
+            accumulator: Duration::ZERO,
+            statistics: FrameStatistics::default(),
+        }
+    }
+
+    /// Start a new frame, should be called exactly once at the start of every frame.
+    pub fn tick(&mut self) -> FrameTime {
+        let now = self.clock.now();
+        let delta = match self.last_tick {
+            // A clock set back in time gives an empty frame rather than a negative one
+            Some(last_tick) => now.saturating_sub(last_tick),
+            None => Duration::ZERO,
+        };
+        if self.last_tick.is_some() {
+            self.statistics.record(delta);
+        }
+        self.last_tick = Some(now);
+
+        self.accumulator += delta;
+        let steps = self.accumulator.as_nanos() / self.fixed_timestep.as_nanos();
+        self.accumulator = Duration::from_nanos((self.accumulator.as_nanos() % self.fixed_timestep.as_nanos()) as u64);
+        let fixed_steps = steps.min(MAX_FIXED_STEPS_PER_FRAME as u128) as u32;
+
+        let frame_time = FrameTime {
+            elapsed: now,
+            delta,
+            frame_index: self.frame_index,
+            fixed_timestep: self.fixed_timestep,
+            fixed_steps,
+            fixed_step_alpha: self.accumulator.as_secs_f32() / self.fixed_timestep.as_secs_f32(),
+        };
+        self.frame_index += 1;
+
+        frame_time
+    }
+
+    /// Rolling statistics over the last frames.
+    pub fn statistics(&self) -> &FrameStatistics {
+        &self.statistics
+    }
+}
+
+impl Default for FrameClock {
+    fn default() -> Self {
+        FrameClock::new(DEFAULT_FIXED_TIMESTEP)
+    }
+}
+
+/// FPS and frame time statistics over the last `STATISTICS_WINDOW` frames.
+#[derive(Default)]
+pub struct FrameStatistics {
+    frame_times: VecDeque<Duration>,
+    total: Duration,
+}
+
+impl FrameStatistics {
+    fn record(&mut self, frame_time: Duration) {
+        if self.frame_times.len() == STATISTICS_WINDOW {
+            self.total -= self.frame_times.pop_front().unwrap();
+        }
+        self.frame_times.push_back(frame_time);
+        self.total += frame_time;
+    }
+
+    /// Number of frames the statistics are computed over, up to `STATISTICS_WINDOW`.
+    pub fn frame_count(&self) -> usize {
+        self.frame_times.len()
+    }
+
+    /// Average frames per second, 0 until at least one frame was recorded.
+    pub fn fps(&self) -> f32 {
+        if self.total.is_zero() {
+            return 0.0;
+        }
+        self.frame_times.len() as f32 / self.total.as_secs_f32()
+    }
+
+    pub fn average_frame_time(&self) -> Duration {
+        if self.frame_times.is_empty() {
+            return Duration::ZERO;
+        }
+        self.total / self.frame_times.len() as u32
+    }
+
+    pub fn min_frame_time(&self) -> Duration {
+        self.frame_times.iter().copied().min().unwrap_or_default()
+    }
+
+    pub fn max_frame_time(&self) -> Duration {
+        self.frame_times.iter().copied().max().unwrap_or_default()
+    }
+}
+
+impl fmt::Display for FrameStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.1} FPS ({:.2} ms, min {:.2} ms, max {:.2} ms)",
+               self.fps(),
+               self.average_frame_time().as_secs_f64() * 1000.0,
+               self.min_frame_time().as_secs_f64() * 1000.0,
+               self.max_frame_time().as_secs_f64() * 1000.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::ManualClock;
+    use super::*;
+
+    const TIMESTEP: Duration = Duration::from_millis(10);
+
+    fn manual_frame_clock() -> (ManualClock, FrameClock) {
+        let clock = ManualClock::new();
+        (clock.clone(), FrameClock::with_clock(Box::new(clock), TIMESTEP))
+    }
+
+    #[test]
+    fn delta_and_elapsed_follow_the_clock() {
+        let (clock, mut frame_clock) = manual_frame_clock();
+        let first = frame_clock.tick();
+        assert_eq!((first.frame_index, first.elapsed, first.delta), (0, Duration::ZERO, Duration::ZERO));
+
+        clock.advance(Duration::from_millis(16));
+        let second = frame_clock.tick();
+        assert_eq!((second.frame_index, second.elapsed), (1, Duration::from_millis(16)));
+        assert_eq!(second.delta, Duration::from_millis(16));
+
+        clock.advance(Duration::from_millis(4));
+        assert_eq!(frame_clock.tick().delta, Duration::from_millis(4));
+    }
+
+    #[test]
+    fn a_clock_set_back_gives_an_empty_frame() {
+        let (clock, mut frame_clock) = manual_frame_clock();
+        clock.set(Duration::from_secs(2));
+        frame_clock.tick();
+
+        clock.set(Duration::from_secs(1));
+        let frame_time = frame_clock.tick();
+        assert_eq!((frame_time.elapsed, frame_time.delta), (Duration::from_secs(1), Duration::ZERO));
+        assert_eq!(frame_time.fixed_steps, 0);
+    }
+
+    #[test]
+    fn fixed_steps_accumulate_across_frames() {
+        let (clock, mut frame_clock) = manual_frame_clock();
+        frame_clock.tick();
+
+        clock.advance(Duration::from_millis(25));
+        let frame_time = frame_clock.tick();
+        assert_eq!(frame_time.fixed_steps, 2);
+        assert!((frame_time.fixed_step_alpha - 0.5).abs() < 1e-4);
+
+        // The 5 ms left over and 5 more make one step, with nothing left
+        clock.advance(Duration::from_millis(5));
+        let frame_time = frame_clock.tick();
+        assert_eq!(frame_time.fixed_steps, 1);
+        assert_eq!(frame_time.fixed_step_alpha, 0.0);
+    }
+
+    #[test]
+    fn fixed_steps_are_clamped_after_a_stall() {
+        let (clock, mut frame_clock) = manual_frame_clock();
+        frame_clock.tick();
+
+        clock.advance(Duration::from_secs(3));
+        assert_eq!(frame_clock.tick().fixed_steps, MAX_FIXED_STEPS_PER_FRAME);
+        // The rest of the stall is dropped rather than caught up on the next frames
+        clock.advance(TIMESTEP);
+        assert_eq!(frame_clock.tick().fixed_steps, 1);
+    }
+
+    #[test]
+    fn long_stalls_keep_the_exact_leftover() {
+        let clock = ManualClock::new();
+        let mut frame_clock = FrameClock::with_clock(Box::new(clock.clone()), Duration::from_nanos(2));
+        frame_clock.tick();
+
+        // 5 000 000 000 steps, more than a u32 counts, with 1 ns left
+        clock.advance(Duration::from_secs(10) + Duration::from_nanos(1));
+        let frame_time = frame_clock.tick();
+        assert_eq!(frame_time.fixed_steps, MAX_FIXED_STEPS_PER_FRAME);
+        assert_eq!(frame_time.fixed_step_alpha, 0.5);
+    }
+
+    #[test]
+    fn statistics_average_the_recorded_frames() {
+        let (clock, mut frame_clock) = manual_frame_clock();
+        frame_clock.tick();
+        assert_eq!(frame_clock.statistics().fps(), 0.0);
+
+        for frame_time in [10, 20, 30] {
+            clock.advance(Duration::from_millis(frame_time));
+            frame_clock.tick();
+        }
+        let statistics = frame_clock.statistics();
+        assert_eq!(statistics.frame_count(), 3);
+        assert_eq!(statistics.average_frame_time(), Duration::from_millis(20));
+        assert_eq!(statistics.min_frame_time(), Duration::from_millis(10));
+        assert_eq!(statistics.max_frame_time(), Duration::from_millis(30));
+        assert!((statistics.fps() - 50.0).abs() < 1e-3);
+
+        for _ in 0..STATISTICS_WINDOW {
+            clock.advance(Duration::from_millis(5));
+            frame_clock.tick();
+        }
+        let statistics = frame_clock.statistics();
+        assert_eq!(statistics.frame_count(), STATISTICS_WINDOW);
+        assert_eq!(statistics.average_frame_time(), Duration::from_millis(5));
+    }
+}
@@ -0,0 +1,195 @@
+/*
+Offscreen framebuffers.
+
+A framebuffer object (FBO) is a render target that lives entirely on the GPU: once it is bound,
+everything the scenes draw goes into its attachments instead of the window. This is how the
+headless mode renders without a window at all.
+
+With MSAA the scene is drawn into a multisampled framebuffer, which can't be read directly, so
+`resolve` blits it into a regular single-sampled one first.
+ */
+
+use std::ffi::c_void;
+use crate::image::Image;
+
+pub struct OffscreenFramebuffer {
+    width: i32,
+    height: i32,
+    samples: i32,
+    framebuffer: u32,
+    /// Color and depth-stencil renderbuffers of `framebuffer`, the second one is only attached
+    /// when there is a depth or a stencil buffer
+    renderbuffers: [u32; 2],
+    /// Single-sampled framebuffer `framebuffer` is resolved into, only used with MSAA
+    resolve_framebuffer: u32,
+    resolve_renderbuffer: u32,
+}
+
+impl OffscreenFramebuffer {
+    /// Create a framebuffer with an RGBA8 color attachment, and a depth and stencil one of the
+    /// given sizes.
+    ///
+    /// # Arguments
+    /// * `width`, `height` - Size in pixels
+    /// * `samples` - Samples per pixel for MSAA, 0 turns it off
+    /// * `depth_bits`, `stencil_bits` - Bits of the depth and stencil buffers, 0 for none. Only the
+    ///   sizes of `depth_stencil_format` are supported
+    pub fn new(width: u32, height: u32, samples: u8, depth_bits: u8, stencil_bits: u8) -> Result<OffscreenFramebuffer, String> {
+        let depth_stencil = depth_stencil_format(depth_bits, stencil_bits)?;
+        let mut framebuffer = OffscreenFramebuffer {
+            width: width as i32,
+            height: height as i32,
+            samples: samples as i32,
+            framebuffer: 0,
+            renderbuffers: [0; 2],
+            resolve_framebuffer: 0,
+            resolve_renderbuffer: 0,
+        };
+
+        unsafe {
+            gl::GenFramebuffers(1, &mut framebuffer.framebuffer);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer.framebuffer);
+            gl::GenRenderbuffers(2, framebuffer.renderbuffers.as_mut_ptr());
+            framebuffer.attach_renderbuffer(framebuffer.renderbuffers[0], gl::RGBA8,
+                                            gl::COLOR_ATTACHMENT0, framebuffer.samples);
+            if let Some((format, attachment)) = depth_stencil {
+                framebuffer.attach_renderbuffer(framebuffer.renderbuffers[1], format, attachment, framebuffer.samples);
+            }
+            check_framebuffer_status()?;
+
+            if samples > 0 {
+                gl::GenFramebuffers(1, &mut framebuffer.resolve_framebuffer);
+                gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer.resolve_framebuffer);
+                gl::GenRenderbuffers(1, &mut framebuffer.resolve_renderbuffer);
+                framebuffer.attach_renderbuffer(framebuffer.resolve_renderbuffer, gl::RGBA8,
+                                                gl::COLOR_ATTACHMENT0, 0);
+                check_framebuffer_status()?;
+            }
+        }
+
+        framebuffer.bind();
+        Ok(framebuffer)
+    }
+
+    unsafe fn attach_renderbuffer(&self, renderbuffer: u32, format: u32, attachment: u32, samples: i32) {
+        gl::BindRenderbuffer(gl::RENDERBUFFER, renderbuffer);
+        if samples > 0 {
+            gl::RenderbufferStorageMultisample(gl::RENDERBUFFER, samples, format, self.width, self.height);
+        } else {
+            gl::RenderbufferStorage(gl::RENDERBUFFER, format, self.width, self.height);
+        }
+        gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, attachment, gl::RENDERBUFFER, renderbuffer);
+    }
+
+    /// Draw into this framebuffer from now on, and make the viewport cover all of it.
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+            gl::Viewport(0, 0, self.width, self.height);
+        }
+    }
+
+    /// Make the last frame readable, by resolving the samples of a multisampled framebuffer.
+    /// Does nothing without MSAA.
+    pub fn resolve(&self) {
+        if self.samples == 0 {
+            return;
+        }
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.framebuffer);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, self.resolve_framebuffer);
+            gl::BlitFramebuffer(0, 0, self.width, self.height, 0, 0, self.width, self.height,
+                                gl::COLOR_BUFFER_BIT, gl::NEAREST);
+        }
+        self.bind();
+    }
+
+    /// The framebuffer holding the last resolved frame, to read pixels from.
+    pub fn readable_framebuffer(&self) -> u32 {
+        if self.samples == 0 { self.framebuffer } else { self.resolve_framebuffer }
+    }
+
+    /// Read the last resolved frame back to the CPU.
+    pub fn read_pixels(&self) -> Image {
+        read_pixels(self.readable_framebuffer(), self.width(), self.height())
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height as u32
+    }
+}
+
+/// The renderbuffer format and attachment of a depth and stencil buffer of the given sizes, None
+/// when both are 0.
+fn depth_stencil_format(depth_bits: u8, stencil_bits: u8) -> Result<Option<(u32, u32)>, String> {
+    Ok(Some(match (depth_bits, stencil_bits) {
+        (0, 0) => return Ok(None),
+        (16, 0) => (gl::DEPTH_COMPONENT16, gl::DEPTH_ATTACHMENT),
+        (24, 0) => (gl::DEPTH_COMPONENT24, gl::DEPTH_ATTACHMENT),
+        (32, 0) => (gl::DEPTH_COMPONENT32F, gl::DEPTH_ATTACHMENT),
+        (0, 8) => (gl::STENCIL_INDEX8, gl::STENCIL_ATTACHMENT),
+        (24, 8) => (gl::DEPTH24_STENCIL8, gl::DEPTH_STENCIL_ATTACHMENT),
+        (32, 8) => (gl::DEPTH32F_STENCIL8, gl::DEPTH_STENCIL_ATTACHMENT),
+        _ => return Err(format!("Offscreen framebuffers can't have {} bits of depth and {} bits of stencil, only \
+                                 24/8 or 32/8, 16, 24 or 32 bits of depth alone, 8 bits of stencil alone, or none",
+                                depth_bits, stencil_bits)),
+    }))
+}
+
+impl Drop for OffscreenFramebuffer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl::DeleteFramebuffers(1, &self.framebuffer);
+            gl::DeleteRenderbuffers(2, self.renderbuffers.as_ptr());
+            if self.samples > 0 {
+                gl::DeleteFramebuffers(1, &self.resolve_framebuffer);
+                gl::DeleteRenderbuffers(1, &self.resolve_renderbuffer);
+            }
+        }
+    }
+}
+
+/// Read the content of a framebuffer back to the CPU.
+///
+/// # Arguments
+/// * `framebuffer` - The framebuffer to read, 0 for the window's default framebuffer (in which
+///   case it must be called before the buffers are swapped)
+/// * `width`, `height` - Size of the framebuffer in pixels
+pub fn read_pixels(framebuffer: u32, width: u32, height: u32) -> Image {
+    let row_length = width as usize * 4;
+    let mut pixels = vec![0u8; row_length * height as usize];
+
+    unsafe {
+        let mut previous_read_framebuffer = 0;
+        gl::GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut previous_read_framebuffer);
+
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, framebuffer);
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+        gl::ReadPixels(0, 0, width as i32, height as i32, gl::RGBA, gl::UNSIGNED_BYTE,
+                       pixels.as_mut_ptr() as *mut c_void);
+
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, previous_read_framebuffer as u32);
+    }
+
+    // OpenGL's first row is the bottom of the picture, but images start at the top
+    let flipped_pixels = pixels.chunks_exact(row_length).rev().flatten().copied().collect();
+
+    Image {
+        width,
+        height,
+        pixels: flipped_pixels,
+    }
+}
+
+unsafe fn check_framebuffer_status() -> Result<(), String> {
+    let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+    if status != gl::FRAMEBUFFER_COMPLETE {
+        return Err(format!("The offscreen framebuffer is incomplete (status 0x{:x})", status));
+    }
+    Ok(())
+}
@@ -0,0 +1,219 @@
+/*
+Headless rendering, without a window.
+
+The GL context is created through EGL on Mesa's "surfaceless" platform, which needs no display
+server and no GPU: with the llvmpipe software rasterizer it runs on any CI machine. Since there is
+no window there is no default framebuffer either, so everything is drawn into an
+`OffscreenFramebuffer` of the requested size.
+
+Force the software rasterizer with `LIBGL_ALWAYS_SOFTWARE=1` to get the same pixels everywhere.
+ */
+
+use std::ffi::c_void;
+use std::sync::Once;
+use khronos_egl as egl;
+use crate::clock::FixedStepClock;
+use crate::context_config::{ContextConfig, Profile};
+use crate::frame_clock::{FrameClock, DEFAULT_FIXED_TIMESTEP};
+use crate::framebuffer::OffscreenFramebuffer;
+use crate::image::Image;
+use crate::scene::Scene;
+use crate::shader::ShaderError;
+
+/// From the EGL_MESA_platform_surfaceless extension, which khronos-egl doesn't define.
+const PLATFORM_SURFACELESS_MESA: egl::Enum = 0x31DD;
+
+/// The GL function pointers are global, and EGL hands out the same ones to every context, so
+/// they are only loaded once even when several threads (such as tests) create contexts.
+static LOAD_GL_FUNCTIONS: Once = Once::new();
+
+pub struct HeadlessContext {
+    display: egl::Display,
+    context: egl::Context,
+    /// Only created when the driver can't make a context current without any surface.
+    surface: Option<egl::Surface>,
+    /// An Option only so it can be dropped while the context is still current.
+    framebuffer: Option<OffscreenFramebuffer>,
+}
+
+impl HeadlessContext {
+    /// Create a GL context without a window, load the GL functions, and bind an offscreen
+    /// framebuffer of the given size to draw into.
+    ///
+    /// # Arguments
+    /// * `config` - The context to request, the swap interval is ignored since nothing is shown
+    /// * `width`, `height` - Size of the offscreen framebuffer in pixels
+    pub fn new(config: &ContextConfig, width: u32, height: u32) -> Result<HeadlessContext, String> {
+        let display = open_display()?;
+        egl::API.initialize(display)
+            .map_err(|error| format!("Could not initialize EGL: {}", error))?;
+        egl::API.bind_api(egl::OPENGL_API)
+            .map_err(|error| format!("EGL does not support desktop OpenGL: {}", error))?;
+
+        let config_attributes = [
+            egl::SURFACE_TYPE, egl::PBUFFER_BIT,
+            egl::RENDERABLE_TYPE, egl::OPENGL_BIT,
+            egl::NONE,
+        ];
+        let egl_config = egl::API.choose_first_config(display, &config_attributes)
+            .map_err(|error| error.to_string())
+            .and_then(|egl_config| egl_config.ok_or(String::from("no matching config")))
+            .map_err(|error| format!("Could not find an EGL config for OpenGL: {}", error))?;
+
+        let mut context_flags = Vec::new();
+        if config.debug {
+            context_flags.extend([egl::CONTEXT_OPENGL_DEBUG, egl::TRUE as egl::Int]);
+        }
+        if config.forward_compatible {
+            context_flags.extend([egl::CONTEXT_OPENGL_FORWARD_COMPATIBLE, egl::TRUE as egl::Int]);
+        }
+        let profile_bit = match config.profile {
+            Profile::Core => egl::CONTEXT_OPENGL_CORE_PROFILE_BIT,
+            Profile::Compatibility => egl::CONTEXT_OPENGL_COMPATIBILITY_PROFILE_BIT,
+        };
+        let context_attributes: Vec<egl::Int> = [
+            egl::CONTEXT_MAJOR_VERSION, config.major_version as egl::Int,
+            egl::CONTEXT_MINOR_VERSION, config.minor_version as egl::Int,
+            egl::CONTEXT_OPENGL_PROFILE_MASK, profile_bit,
+        ].into_iter().chain(context_flags).chain([egl::NONE]).collect();
+        let context = egl::API.create_context(display, egl_config, None, &context_attributes)
+            .map_err(|error| format!("Could not create an OpenGL {} context: {}", config, error))?;
+
+        // Prefer not having a surface at all, we never draw to it anyway. Otherwise fall back to
+        // a tiny pbuffer, just so there is something to make current.
+        let mut surface = None;
+        if egl::API.make_current(display, None, None, Some(context)).is_err() {
+            let pbuffer_attributes = [egl::WIDTH, 1, egl::HEIGHT, 1, egl::NONE];
+            let pbuffer = egl::API.create_pbuffer_surface(display, egl_config, &pbuffer_attributes)
+                .map_err(|error| format!("Could not create a pbuffer surface: {}", error))?;
+            egl::API.make_current(display, Some(pbuffer), Some(pbuffer), Some(context))
+                .map_err(|error| format!("Could not make the headless context current: {}", error))?;
+            surface = Some(pbuffer);
+        }
+
+        LOAD_GL_FUNCTIONS.call_once(|| gl::load_with(|name| match egl::API.get_proc_address(name) {
+            Some(function) => function as *const c_void,
+            None => std::ptr::null(),
+        }));
+
+        let mut headless_context = HeadlessContext {
+            display,
+            context,
+            surface,
+            framebuffer: None,
+        };
+        config.check_current_context()?;
+        headless_context.framebuffer = Some(OffscreenFramebuffer::new(width, height, config.msaa_samples,
+                                                                         config.depth_bits, config.stencil_bits)?);
+
+        Ok(headless_context)
+    }
+
+    /// The framebuffer everything is drawn into.
+    pub fn framebuffer(&self) -> &OffscreenFramebuffer {
+        self.framebuffer.as_ref().unwrap()
+    }
+
+    /// The headless equivalent of swapping the window buffers: wait for the frame to be fully
+    /// drawn, and make it readable.
+    pub fn finish_frame(&self) {
+        self.framebuffer().resolve();
+        unsafe { gl::Finish(); }
+    }
+
+    /// Read the last finished frame back to the CPU.
+    pub fn read_pixels(&self) -> Image {
+        self.framebuffer().read_pixels()
+    }
+
+    /// Render a single frame of a scene, at the time it would be drawn at in an animation
+    /// running at `rate` frames per second, so the result is always the same.
+    ///
+    /// The scene is set up before the frame and torn down after it, or right away when its setup
+    /// fails.
+    ///
+    /// # Arguments
+    /// * `scene` - A scene that was not set up yet
+    /// * `frame` - Index of the frame to render, such as 120 for 2 seconds at 60 Hz
+    /// * `rate` - Frames per second of the animation
+    pub fn render_frame(&self, scene: &mut dyn Scene, frame: u64, rate: u32) -> Result<Image, ShaderError> {
+        let clock = FixedStepClock::starting_at_frame(rate, frame);
+        let mut frame_clock = FrameClock::with_clock(Box::new(clock), DEFAULT_FIXED_TIMESTEP);
+
+        if let Err(error) = scene.setup() {
+            scene.teardown();
+            return Err(error);
+        }
+        scene.frame(&frame_clock.tick());
+        self.finish_frame();
+        scene.teardown();
+
+        Ok(self.read_pixels())
+    }
+}
+
+impl Drop for HeadlessContext {
+    fn drop(&mut self) {
+        // The framebuffer must be deleted while its context is still current
+        self.framebuffer = None;
+        let _ = egl::API.make_current(self.display, None, None, None);
+        if let Some(surface) = self.surface {
+            let _ = egl::API.destroy_surface(self.display, surface);
+        }
+        let _ = egl::API.destroy_context(self.display, self.context);
+        // The display is not terminated: it is shared by every context of the process, and
+        // other threads may still be using it.
+    }
+}
+
+/// Mesa's surfaceless platform if it is there, the default display otherwise.
+fn open_display() -> Result<egl::Display, String> {
+    let surfaceless_display = unsafe {
+        egl::API.get_platform_display(PLATFORM_SURFACELESS_MESA, egl::DEFAULT_DISPLAY, &[egl::ATTRIB_NONE])
+    };
+    match surfaceless_display {
+        Ok(display) => Ok(display),
+        Err(_) => unsafe { egl::API.get_display(egl::DEFAULT_DISPLAY) }
+            .ok_or(String::from("Could not open an EGL display")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn framebuffer_parameter(attachment: u32, parameter: u32) -> i32 {
+        let mut value = 0;
+        unsafe { gl::GetFramebufferAttachmentParameteriv(gl::FRAMEBUFFER, attachment, parameter, &mut value); }
+        value
+    }
+
+    #[test]
+    fn contexts_older_than_3_0_are_checked_too() {
+        let config = ContextConfig {
+            major_version: 2,
+            minor_version: 1,
+            profile: Profile::Compatibility,
+            ..ContextConfig::default()
+        };
+        HeadlessContext::new(&config, 1, 1).unwrap();
+    }
+
+    #[test]
+    fn framebuffers_have_the_depth_and_stencil_bits_asked_for() {
+        let config = ContextConfig { depth_bits: 16, stencil_bits: 0, ..ContextConfig::default() };
+        let context = HeadlessContext::new(&config, 1, 1).unwrap();
+        assert_eq!(framebuffer_parameter(gl::DEPTH_ATTACHMENT, gl::FRAMEBUFFER_ATTACHMENT_DEPTH_SIZE), 16);
+        assert_eq!(framebuffer_parameter(gl::STENCIL_ATTACHMENT, gl::FRAMEBUFFER_ATTACHMENT_OBJECT_TYPE) as u32, gl::NONE);
+        drop(context);
+
+        let context = HeadlessContext::new(&ContextConfig::default(), 1, 1).unwrap();
+        assert_eq!(framebuffer_parameter(gl::DEPTH_ATTACHMENT, gl::FRAMEBUFFER_ATTACHMENT_DEPTH_SIZE), 24);
+        assert_eq!(framebuffer_parameter(gl::STENCIL_ATTACHMENT, gl::FRAMEBUFFER_ATTACHMENT_STENCIL_SIZE), 8);
+        drop(context);
+
+        let config = ContextConfig { depth_bits: 12, ..ContextConfig::default() };
+        let error = HeadlessContext::new(&config, 1, 1).err().unwrap();
+        assert!(error.starts_with("Offscreen framebuffers can't have 12 bits of depth and 8 bits of stencil"), "{}", error);
+    }
+}
@@ -0,0 +1,73 @@
+/*
+Shader hot reload, in debug builds only.
+
+Debug builds read the shaders from src/shaders at runtime (see `ShaderFile::code`), and the host
+watches that directory. The host polls the changes once per frame, between two frames, and
+`ShaderWatcher::poll` rebuilds every live program built from a changed file right away, so a frame
+is always drawn with the same versions of the programs from start to end. A program whose new
+version doesn't compile or link keeps running with its previous version, and the error is printed.
+
+Rebuilding a program gives it a new GL handle, with all its uniforms back to their defaults.
+ */
+
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use notify::{RecursiveMode, Watcher};
+use crate::shader_library::SHADER_DIRECTORY;
+
+/// Watches src/shaders for changes.
+pub struct ShaderWatcher {
+    /// Stops watching when dropped.
+    _watcher: notify::RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+    directory: PathBuf,
+}
+
+impl ShaderWatcher {
+    pub fn new() -> Result<ShaderWatcher, String> {
+        // Events carry absolute paths, possibly through symbolic links, so compare canonical ones
+        let directory = Path::new(SHADER_DIRECTORY).canonicalize()
+            .map_err(|error| format!("Could not find the shaders directory {}: {}", SHADER_DIRECTORY, error))?;
+
+        let (sender, events) = channel();
+        let mut watcher = notify::recommended_watcher(sender)
+            .map_err(|error| format!("Could not watch the shaders: {}", error))?;
+        watcher.watch(&directory, RecursiveMode::Recursive)
+            .map_err(|error| format!("Could not watch {}: {}", directory.display(), error))?;
+
+        Ok(ShaderWatcher {
+            _watcher: watcher,
+            events,
+            directory,
+        })
+    }
+
+    /// Rebuild the programs built from the files that changed since the last call. Called by the
+    /// host between frames, on the thread the programs were created on.
+    ///
+    /// Returns the paths of the changed files, relative to src/shaders.
+    pub fn poll(&self) -> Vec<String> {
+        let mut changed_paths = Vec::new();
+        for event in self.events.try_iter() {
+            match event {
+                // Reading the shaders (which reloading them does) is reported too
+                Ok(event) if event.kind.is_access() => {}
+                // Editors save in many different ways (in place, through a temporary file that is
+                // renamed, etc...), so any other event on a file counts as a change
+                Ok(event) => changed_paths.extend(event.paths.iter().filter_map(|path| {
+                    let relative_path = path.strip_prefix(&self.directory).ok()?;
+                    Some(relative_path.to_string_lossy().replace('\\', "/"))
+                })),
+                Err(error) => eprintln!("Error while watching the shaders: {}", error),
+            }
+        }
+        changed_paths.sort();
+        changed_paths.dedup();
+
+        if !changed_paths.is_empty() {
+            crate::shader::reload_programs(&changed_paths);
+        }
+
+        changed_paths
+    }
+}
@@ -0,0 +1,126 @@
+/*
+CPU side images, for the pixels read back from a framebuffer.
+ */
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+/// An RGBA8 image whose first row is the top of the picture.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    /// 4 bytes per pixel, row after row.
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    /// The RGBA value of a pixel, (0, 0) is the top left corner.
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let offset = ((y * self.width + x) * 4) as usize;
+        self.pixels[offset..offset + 4].try_into().unwrap()
+    }
+
+    /// Load a PNG, converting it to RGBA8 if needed.
+    pub fn load_png(path: &Path) -> Result<Image, String> {
+        let describe = |error: &dyn std::fmt::Display| format!("Could not load {}: {}", path.display(), error);
+
+        let file = File::open(path).map_err(|error| describe(&error))?;
+        let mut decoder = png::Decoder::new(BufReader::new(file));
+        decoder.set_transformations(png::Transformations::normalize_to_color8() | png::Transformations::ALPHA);
+        let mut reader = decoder.read_info().map_err(|error| describe(&error))?;
+
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).map_err(|error| describe(&error))?;
+        pixels.truncate(info.buffer_size());
+
+        // Grayscale images are still 2 bytes per pixel after the transformations
+        if info.color_type == png::ColorType::GrayscaleAlpha {
+            pixels = pixels.chunks_exact(2)
+                .flat_map(|gray_alpha| [gray_alpha[0], gray_alpha[0], gray_alpha[0], gray_alpha[1]])
+                .collect();
+        }
+
+        Ok(Image {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+
+    /// Compare this image to an expected one, pixel by pixel.
+    ///
+    /// # Arguments
+    /// * `expected` - The reference image, must have the same size
+    /// * `tolerance` - The largest difference allowed in any channel of a pixel before the pixel
+    ///   counts as mismatched
+    pub fn compare(&self, expected: &Image, tolerance: u8) -> Result<ImageComparison, String> {
+        if (self.width, self.height) != (expected.width, expected.height) {
+            return Err(format!("Expected a {}x{} image, got a {}x{} one",
+                               expected.width, expected.height, self.width, self.height));
+        }
+
+        let mut mismatched_pixels = 0;
+        let mut max_difference = 0;
+        let mut diff_pixels = Vec::with_capacity(self.pixels.len());
+        for (actual, expected) in self.pixels.chunks_exact(4).zip(expected.pixels.chunks_exact(4)) {
+            let difference = actual.iter().zip(expected)
+                .map(|(actual, expected)| actual.abs_diff(*expected))
+                .max()
+                .unwrap();
+            max_difference = max_difference.max(difference);
+
+            if difference > tolerance {
+                // Mismatches in bright red
+                mismatched_pixels += 1;
+                diff_pixels.extend([255, 0, 0, 255]);
+            } else {
+                // Everything else as a faded grayscale version of the expected image
+                let luminance = (expected[0] as u32 * 3 + expected[1] as u32 * 6 + expected[2] as u32) / 10;
+                let faded = (luminance / 3) as u8;
+                diff_pixels.extend([faded, faded, faded, 255]);
+            }
+        }
+
+        Ok(ImageComparison {
+            mismatched_pixels,
+            max_difference,
+            diff: Image {
+                width: self.width,
+                height: self.height,
+                pixels: diff_pixels,
+            },
+        })
+    }
+
+    pub fn save_png(&self, path: &Path) -> Result<(), String> {
+        let describe = |error: &dyn std::fmt::Display| format!("Could not save {}: {}", path.display(), error);
+
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory).map_err(|error| describe(&error))?;
+        }
+        let file = File::create(path).map_err(|error| describe(&error))?;
+
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(|error| describe(&error))?;
+        writer.write_image_data(&self.pixels).map_err(|error| describe(&error))
+    }
+}
+
+pub struct ImageComparison {
+    /// Number of pixels that are further than the tolerance from the expected image.
+    pub mismatched_pixels: usize,
+    /// The largest difference found in any channel of any pixel.
+    pub max_difference: u8,
+    /// The expected image faded to gray, with the mismatched pixels in red.
+    pub diff: Image,
+}
+
+impl ImageComparison {
+    pub fn matches(&self) -> bool {
+        self.mismatched_pixels == 0
+    }
+}
@@ -1,8 +1,30 @@
-#![feature(core_intrinsics)]
 extern crate sdl2;
 
-mod utils;
+pub mod binary_cache;
+pub mod clock;
+pub mod compute;
+pub mod context_config;
+pub mod diagnostics;
+pub mod frame_clock;
+pub mod framebuffer;
+#[cfg(target_os = "linux")]
+pub mod headless;
+#[cfg(debug_assertions)]
+pub mod hot_reload;
+pub mod image;
+pub mod pixel_probe;
+pub mod preprocessor;
+pub mod reflection;
+pub mod scene;
+pub mod shader;
+pub mod shader_library;
+pub mod uniform_buffer;
+pub mod vertex_buffer;
 
 pub mod hello_triangle;
 pub mod chapter5_exercises;
 pub mod chapter6_exercises;
+pub mod chapter30_exercises;
+pub mod compute_exercises;
+pub mod exercises;
+pub mod cli;
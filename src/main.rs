@@ -3,24 +3,61 @@ extern crate opengl;
 extern crate sdl2;
 
 use std::ffi::c_void;
+use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::time::Duration;
 
 use sdl2::event::{Event, WindowEvent};
-use sdl2::video::Window;
+use sdl2::keyboard::Keycode;
+use sdl2::video::{GLProfile, Window};
+use sdl2::{EventPump, VideoSubsystem};
 
-use opengl::chapter5_exercises::{exercise1, exercise2, exercise3};
-use opengl::hello_triangle::draw_triangle;
-use opengl::chapter6_exercises::{glowing_green_triangle, more_attributes};
+use opengl::binary_cache;
+use opengl::cli::{Arguments, USAGE};
+use opengl::exercises::{find_exercise_index, EXERCISES};
+use opengl::clock::FixedStepClock;
+use opengl::context_config::{current_renderer, ContextConfig, Profile, SwapInterval};
+use opengl::frame_clock::{FrameClock, DEFAULT_FIXED_TIMESTEP};
+#[cfg(debug_assertions)]
+use opengl::hot_reload::ShaderWatcher;
+use opengl::framebuffer::read_pixels;
+use opengl::image::Image;
+use opengl::scene::Scene;
+use opengl::shader::ShaderStage;
+use opengl::shader_library::SHADERS;
+#[cfg(debug_assertions)]
+use opengl::shader_library::shaders_of_file;
+#[cfg(target_os = "linux")]
+use opengl::headless::HeadlessContext;
 
-const INITIAL_WINDOW_WIDTH: u32 = 800;
-const INITIAL_WINDOW_HEIGHT: u32 = 600;
+/// How often the FPS shown in the window title is refreshed
+const TITLE_REFRESH_INTERVAL: Duration = Duration::from_millis(500);
 
-fn handle_events(sdl_context: &sdl2::Sdl) {
-    let mut event_pump = sdl_context.event_pump().unwrap();
+/// What the host loop should do after handling the events of a frame
+enum HostAction {
+    Continue,
+    NextScene,
+    PreviousScene,
+    Screenshot,
+    Quit,
+}
+
+fn handle_events(event_pump: &mut EventPump) -> HostAction {
+    let mut action = HostAction::Continue;
     for event in event_pump.poll_iter() {
         match event {
             Event::Quit { .. } => {
-                exit(0);
+                // Quitting wins over anything else that happened in this frame
+                return HostAction::Quit;
+            }
+            Event::KeyDown { keycode: Some(Keycode::PageDown), .. } => {
+                action = HostAction::NextScene;
+            }
+            Event::KeyDown { keycode: Some(Keycode::PageUp), .. } => {
+                action = HostAction::PreviousScene;
+            }
+            Event::KeyDown { keycode: Some(Keycode::F12), .. } => {
+                action = HostAction::Screenshot;
             }
             Event::Window { win_event, .. } => {
                 if let WindowEvent::Resized(width, height) = win_event {
@@ -31,20 +68,76 @@ fn handle_events(sdl_context: &sdl2::Sdl) {
             _ => { println!("Unhandled event event.") }
         }
     }
+
+    action
+}
+
+
+/// Tell SDL which GL context to create, must be called before the window is opened
+fn set_gl_attributes(video: &VideoSubsystem, config: &ContextConfig) {
+    let gl_attr = video.gl_attr();
+    gl_attr.set_context_version(config.major_version, config.minor_version);
+    gl_attr.set_context_profile(match config.profile {
+        Profile::Core => GLProfile::Core,
+        Profile::Compatibility => GLProfile::Compatibility,
+    });
+
+    let mut context_flags = gl_attr.set_context_flags();
+    if config.forward_compatible {
+        context_flags.forward_compatible();
+    }
+    if config.debug {
+        context_flags.debug();
+    }
+    context_flags.set();
+
+    gl_attr.set_depth_size(config.depth_bits);
+    gl_attr.set_stencil_size(config.stencil_bits);
+    if config.msaa_samples > 0 {
+        gl_attr.set_multisample_buffers(1);
+        gl_attr.set_multisample_samples(config.msaa_samples);
+    }
+}
+
+fn set_swap_interval(video: &VideoSubsystem, swap_interval: SwapInterval) {
+    let result = match swap_interval {
+        SwapInterval::Immediate => video.gl_set_swap_interval(sdl2::video::SwapInterval::Immediate),
+        SwapInterval::VSync => video.gl_set_swap_interval(sdl2::video::SwapInterval::VSync),
+        // Not every driver supports adaptive vsync, regular vsync is the closest thing to it
+        SwapInterval::Adaptive => video.gl_set_swap_interval(sdl2::video::SwapInterval::LateSwapTearing)
+            .or_else(|_| video.gl_set_swap_interval(sdl2::video::SwapInterval::VSync)),
+    };
+
+    if let Err(message) = result {
+        eprintln!("Could not set the swap interval to {:?}: {}", swap_interval, message);
+    }
+}
+
+fn save_screenshot(image: &Image, path: &Path) {
+    match image.save_png(path) {
+        Ok(()) => println!("Saved a screenshot to {}", path.display()),
+        Err(message) => eprintln!("{}", message),
+    }
 }
 
+/// Print the error and exit, for errors that happen before there is anything to clean up
+fn fail(message: String) -> ! {
+    eprintln!("{}", message);
+    exit(1);
+}
 
-fn open_window(sdl_context: &sdl2::Sdl) -> Window {
-    let video = sdl_context.video().unwrap();
-    video.window("My first OpenGL App!", INITIAL_WINDOW_WIDTH, INITIAL_WINDOW_HEIGHT)
+fn open_window(sdl_context: &sdl2::Sdl, arguments: &Arguments) -> Result<Window, String> {
+    let video = sdl_context.video()?;
+    set_gl_attributes(&video, &arguments.context);
+    video.window("My first OpenGL App!", arguments.width, arguments.height)
         .resizable()
         .opengl()
         .build()
-        .unwrap()
+        .map_err(|error| error.to_string())
 }
 
 /// Prepare everything needed before we can start rendering some sh*t
-fn initialize_gl<'a>(sdl_context: &sdl2::Sdl) {
+fn initialize_gl(sdl_context: &sdl2::Sdl, width: u32, height: u32) {
     // Since OpenGL is not a library, it is a specification, it is the programmer's
     // responsibility to find the address of each function used from opengl. Instead of
     // loading each function before every usage, we could just load it automatically by
@@ -54,24 +147,231 @@ fn initialize_gl<'a>(sdl_context: &sdl2::Sdl) {
     };
     gl::load_with(load_callback);
     gl::Viewport::load_with(load_callback);
-    unsafe { gl::Viewport(0, 0, INITIAL_WINDOW_WIDTH as i32, INITIAL_WINDOW_HEIGHT as i32); }
+    unsafe { gl::Viewport(0, 0, width as i32, height as i32); }
+}
+
+fn print_exercises() {
+    for exercise in EXERCISES {
+        println!("{}", exercise.id());
+    }
+}
+
+fn print_shaders() {
+    for library_shader in SHADERS {
+        let stage = ShaderStage::of_file(&library_shader.file).map(|stage| stage.to_string()).unwrap_or_default();
+        let defines = match library_shader.defines {
+            [] => String::new(),
+            defines => format!(" with {}", defines.join(", ")),
+        };
+        println!("{:<20} {:<8}  {}{}", library_shader.name, stage, library_shader.file.path, defines);
+    }
 }
 
 fn main() {
+    let arguments = match Arguments::parse(std::env::args().skip(1)) {
+        Ok(arguments) => arguments,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            exit(2);
+        }
+    };
+
+    if arguments.help {
+        println!("{}", USAGE);
+        return;
+    }
+
+    if arguments.list {
+        print_exercises();
+        return;
+    }
+
+    if arguments.list_shaders {
+        print_shaders();
+        return;
+    }
+
+    let exercise_index = match find_exercise_index(&arguments.exercise) {
+        Some(exercise_index) => exercise_index,
+        None => {
+            eprintln!("Unknown exercise '{}', run with --list to see all of them.",
+                      arguments.exercise);
+            exit(2);
+        }
+    };
+
+    if let Some(directory) = &arguments.program_cache {
+        binary_cache::enable(directory);
+    }
+
+    if arguments.headless {
+        run_headless(&arguments, exercise_index);
+    } else {
+        run_windowed(&arguments, exercise_index);
+    }
+}
+
+fn create_frame_clock(arguments: &Arguments) -> FrameClock {
+    match arguments.fixed_rate {
+        Some(rate) => FrameClock::with_clock(Box::new(FixedStepClock::new(rate)), DEFAULT_FIXED_TIMESTEP),
+        None => FrameClock::default(),
+    }
+}
+
+/// Render the exercise into an offscreen framebuffer, without opening a window
+#[cfg(target_os = "linux")]
+fn run_headless(arguments: &Arguments, exercise_index: usize) {
+    let headless_context = HeadlessContext::new(&arguments.context, arguments.width, arguments.height)
+        .unwrap_or_else(|message| fail(message));
+    println!("Running headless on {}", current_renderer());
+
+    let mut scene = (EXERCISES[exercise_index].create)();
+    if let Err(error) = scene.setup() {
+        scene.teardown();
+        fail(format!("Could not set up {}: {}", EXERCISES[exercise_index].id(), error));
+    }
+
+    let mut frame_clock = create_frame_clock(arguments);
+    for _ in 0..arguments.frames.unwrap_or(1) {
+        let frame_time = frame_clock.tick();
+        scene.frame(&frame_time);
+        headless_context.finish_frame();
+
+        if arguments.screenshot_frame_index() == Some(frame_time.frame_index) {
+            save_screenshot(&headless_context.read_pixels(), arguments.screenshot.as_ref().unwrap());
+        }
+    }
+
+    scene.teardown();
+    println!("Last {} frames: {}", frame_clock.statistics().frame_count(), frame_clock.statistics());
+}
+
+#[cfg(not(target_os = "linux"))]
+fn run_headless(_arguments: &Arguments, _exercise_index: usize) {
+    fail(String::from("Headless rendering is only supported on Linux"));
+}
+
+/// Create and set up the scene of an exercise. An exercise whose setup fails (such as a compute
+/// shader on an OpenGL 3.3 context) is skipped with its diagnostics, and the next ones in the
+/// direction of `step` are tried instead.
+///
+/// # Arguments
+/// * `exercise_index` - Index of the exercise to try first
+/// * `step` - Added to the index to get the next exercise to try, modulo the number of exercises
+fn set_up_scene(mut exercise_index: usize, step: usize) -> (usize, Box<dyn Scene>) {
+    for _ in 0..EXERCISES.len() {
+        let mut scene = (EXERCISES[exercise_index].create)();
+        match scene.setup() {
+            Ok(()) => return (exercise_index, scene),
+            Err(error) => {
+                eprintln!("Could not set up {}, skipping it: {}", EXERCISES[exercise_index].id(), error);
+                scene.teardown();
+                exercise_index = (exercise_index + step) % EXERCISES.len();
+            }
+        }
+    }
+    fail(String::from("None of the exercises could be set up"));
+}
+
+fn run_windowed(arguments: &Arguments, exercise_index: usize) {
     // Initialize the SDL2 context
     let sdl_context = sdl2::init().unwrap();
 
     // Initialize & Open a new window
-    let window = open_window(&sdl_context);
+    let mut window = open_window(&sdl_context, arguments)
+        .unwrap_or_else(|message| fail(format!(
+            "Could not open a window for an OpenGL {} context: {}", arguments.context, message)));
 
-    // Initialize GL context
-    let _gl_context = window.gl_create_context().unwrap();
+    // Initialize GL context. It is declared after the window so it is dropped before it.
+    let _gl_context = window.gl_create_context()
+        .unwrap_or_else(|message| fail(format!(
+            "Could not create an OpenGL {} context: {}", arguments.context, message)));
 
     // Initialize everything needed for GL
-    initialize_gl(&sdl_context);
+    initialize_gl(&sdl_context, arguments.width, arguments.height);
+    if let Err(message) = arguments.context.check_current_context() {
+        fail(message);
+    }
+    println!("Running on {}", current_renderer());
+    set_swap_interval(&sdl_context.video().unwrap(), arguments.context.swap_interval);
+
+    let mut event_pump = sdl_context.event_pump().unwrap();
+
+    // The host owns the loop, so the active scene can be swapped with PageUp/PageDown
+    // without restarting the process.
+    let (mut exercise_index, mut scene) = set_up_scene(exercise_index, 1);
+    window.set_title(&EXERCISES[exercise_index].id()).unwrap();
+
+    // Debug builds pick up the changes to src/shaders without restarting
+    #[cfg(debug_assertions)]
+    let shader_watcher = ShaderWatcher::new()
+        .map_err(|message| eprintln!("Shader hot reload is disabled: {}", message))
+        .ok();
+
+    let mut frame_clock = create_frame_clock(arguments);
+    let mut last_title_refresh = Duration::ZERO;
+
+    loop {
+        let frame_time = frame_clock.tick();
+        if arguments.frames.is_some_and(|frames| frame_time.frame_index >= frames) {
+            break;
+        }
+
+        let mut screenshot_path = match arguments.screenshot_frame_index() {
+            Some(frame_index) if frame_index == frame_time.frame_index => arguments.screenshot.clone(),
+            _ => None,
+        };
+
+        let step = match handle_events(&mut event_pump) {
+            HostAction::Continue => 0,
+            HostAction::Screenshot => {
+                let file_name = format!("{}-{}.png", EXERCISES[exercise_index].id().replace('/', "-"),
+                                        frame_time.frame_index);
+                screenshot_path = Some(PathBuf::from("screenshots").join(file_name));
+                0
+            }
+            HostAction::NextScene => 1 % EXERCISES.len(),
+            HostAction::PreviousScene => EXERCISES.len() - 1,
+            HostAction::Quit => break,
+        };
+
+        if step != 0 {
+            // Tear the current scene down before the next one creates its own GL objects
+            scene.teardown();
+            (exercise_index, scene) = set_up_scene((exercise_index + step) % EXERCISES.len(), step);
+            window.set_title(&EXERCISES[exercise_index].id()).unwrap();
+        }
+
+        #[cfg(debug_assertions)]
+        if let Some(shader_watcher) = &shader_watcher {
+            for path in shader_watcher.poll() {
+                match shaders_of_file(&path).as_slice() {
+                    [] => println!("{} changed", path),
+                    names => println!("{} changed ({})", path, names.join(", ")),
+                }
+            }
+        }
+
+        if frame_time.elapsed - last_title_refresh >= TITLE_REFRESH_INTERVAL {
+            let title = format!("{} - {}", EXERCISES[exercise_index].id(), frame_clock.statistics());
+            window.set_title(&title).unwrap();
+            last_title_refresh = frame_time.elapsed;
+        }
+
+        scene.frame(&frame_time);
+
+        // The back buffer must be read before it is swapped to the screen
+        if let Some(screenshot_path) = screenshot_path {
+            let (width, height) = window.drawable_size();
+            save_screenshot(&read_pixels(0, width, height), &screenshot_path);
+        }
+
+        window.gl_swap_window();
+    }
+
+    println!("Last {} frames: {}", frame_clock.statistics().frame_count(), frame_clock.statistics());
 
-    more_attributes::main(
-        || handle_events(&sdl_context),
-        || window.gl_swap_window(),
-    );
+    // Release the scene's GL objects while the context is still alive. Returning from main
+    // (instead of calling exit) then drops the GL context, the window and SDL in that order.
+    scene.teardown();
 }
@@ -0,0 +1,158 @@
+/*
+Pixel probes, a lightweight alternative to golden images.
+
+Most exercises only draw a few flat shapes, so checking the color of a handful of points (such as
+the center of each triangle and some of the background) says as much as comparing whole images,
+without any reference file to keep up to date.
+
+Probes are placed either in normalized device coordinates, the same space the vertices are written
+in, or in pixels.
+ */
+
+use std::fmt;
+use crate::image::Image;
+
+/// Size of the frames rendered by `assert_pixels`.
+pub const PROBE_WIDTH: u32 = 160;
+pub const PROBE_HEIGHT: u32 = 120;
+
+/// Largest difference allowed in a color channel, about 2 steps of an 8 bits channel. Enough for
+/// flat colors, interpolated ones need more since the probed pixel center is not exactly the
+/// probed point.
+pub const DEFAULT_TOLERANCE: f32 = 2.0 / 255.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProbePosition {
+    /// Normalized device coordinates, (-1, -1) is the bottom left corner and (1, 1) the top right.
+    Ndc(f32, f32),
+    /// Pixel coordinates, (0, 0) is the top left pixel.
+    Pixel(u32, u32),
+}
+
+impl ProbePosition {
+    /// The pixel this position falls in, in an image of the given size.
+    pub fn to_pixel(self, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            ProbePosition::Pixel(x, y) => (x, y),
+            ProbePosition::Ndc(x, y) => {
+                // Same mapping as the viewport transform, but with y pointing down like images
+                let pixel_x = ((x + 1.0) / 2.0 * width as f32).floor() as i64;
+                let pixel_y = ((1.0 - y) / 2.0 * height as f32).floor() as i64;
+                (pixel_x.clamp(0, width as i64 - 1) as u32, pixel_y.clamp(0, height as i64 - 1) as u32)
+            }
+        }
+    }
+}
+
+impl fmt::Display for ProbePosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProbePosition::Ndc(x, y) => write!(f, "NDC ({}, {})", x, y),
+            ProbePosition::Pixel(x, y) => write!(f, "pixel ({}, {})", x, y),
+        }
+    }
+}
+
+/// The color expected at a single point of a frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Probe {
+    pub position: ProbePosition,
+    /// RGB between 0 and 1, as written in the shaders.
+    pub color: [f32; 3],
+}
+
+impl Probe {
+    pub fn ndc(x: f32, y: f32, color: [f32; 3]) -> Probe {
+        Probe { position: ProbePosition::Ndc(x, y), color }
+    }
+
+    pub fn pixel(x: u32, y: u32, color: [f32; 3]) -> Probe {
+        Probe { position: ProbePosition::Pixel(x, y), color }
+    }
+}
+
+/// Check every probe against an image, and describe all the ones that don't match.
+///
+/// # Arguments
+/// * `image` - The rendered frame
+/// * `probes` - The points to check
+/// * `tolerance` - The largest difference allowed in any channel, between 0 and 1
+pub fn check_probes(image: &Image, probes: &[Probe], tolerance: f32) -> Result<(), String> {
+    let mut failures = Vec::new();
+
+    for probe in probes {
+        let (x, y) = probe.position.to_pixel(image.width, image.height);
+        if x >= image.width || y >= image.height {
+            failures.push(format!("{} is outside of the {}x{} frame", probe.position, image.width, image.height));
+            continue;
+        }
+
+        let pixel = image.pixel(x, y);
+        let actual = [pixel[0], pixel[1], pixel[2]].map(|channel| channel as f32 / 255.0);
+        let matches = actual.iter().zip(probe.color)
+            .all(|(actual, expected)| (actual - expected).abs() <= tolerance);
+        if !matches {
+            failures.push(format!("{} (pixel {}, {}): expected {:.3?}, got {:.3?}",
+                                  probe.position, x, y, probe.color, actual));
+        }
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{} of {} probes don't match:\n{}", failures.len(), probes.len(), failures.join("\n")))
+    }
+}
+
+/// Render a single frame of a scene offscreen and check its pixels, panicking with every
+/// mismatch otherwise. Meant for tests.
+///
+/// # Arguments
+/// * `scene` - A scene that was not set up yet
+/// * `frame` - Index of the frame to render at 60 frames per second, for animated scenes
+/// * `probes` - The points to check
+/// * `tolerance` - The largest difference allowed in any channel, between 0 and 1
+#[cfg(target_os = "linux")]
+pub fn assert_pixels(scene: &mut dyn crate::scene::Scene, frame: u64, probes: &[Probe], tolerance: f32) {
+    use crate::context_config::ContextConfig;
+    use crate::headless::HeadlessContext;
+
+    let headless_context = HeadlessContext::new(&ContextConfig::default(), PROBE_WIDTH, PROBE_HEIGHT)
+        .unwrap_or_else(|message| panic!("Could not create a headless context: {}", message));
+    let image = headless_context.render_frame(scene, frame, 60)
+        .unwrap_or_else(|error| panic!("Could not set up the scene: {}", error));
+
+    if let Err(message) = check_probes(&image, probes, tolerance) {
+        panic!("{}", message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ndc_corners_map_to_edge_pixels() {
+        assert_eq!(ProbePosition::Ndc(-1.0, 1.0).to_pixel(160, 120), (0, 0));
+        assert_eq!(ProbePosition::Ndc(1.0, -1.0).to_pixel(160, 120), (159, 119));
+        assert_eq!(ProbePosition::Ndc(0.0, 0.0).to_pixel(160, 120), (80, 60));
+    }
+
+    #[test]
+    fn reports_every_mismatched_probe() {
+        let image = Image {
+            width: 2,
+            height: 1,
+            pixels: vec![255, 128, 51, 255, 0, 0, 0, 255],
+        };
+        let probes = [
+            Probe::pixel(0, 0, [1.0, 0.5, 0.2]),
+            Probe::pixel(1, 0, [1.0, 1.0, 0.0]),
+            Probe::pixel(2, 0, [0.0, 0.0, 0.0]),
+        ];
+
+        let message = check_probes(&image, &probes, DEFAULT_TOLERANCE).unwrap_err();
+        assert!(message.starts_with("2 of 3 probes don't match"), "{}", message);
+        assert!(check_probes(&image, &probes[..1], DEFAULT_TOLERANCE).is_ok());
+    }
+}
@@ -0,0 +1,340 @@
+/*
+A small GLSL preprocessor, for what the driver's own preprocessor can't do on its own: sharing code
+between shaders with
+
+    #include "common/position.glsl"
+
+The path is relative to src/shaders, and the included file is pasted in place of the directive.
+Included files may include other files, but not themselves, even indirectly, and they don't have
+a `#version` line of their own since they are only fragments of a shader.
+
+Pasting code shifts every line after it, so the driver's errors would point to the wrong lines. A
+`#line` directive is emitted before and after every included file, which tells the driver which
+source and line the following code comes from.
+
+The preprocessor also injects `#define`s right after the `#version` line, which turns a single file
+into several variants of a shader, such as `#ifdef YELLOW` in fragment/first_shader.frag.
+ */
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use crate::diagnostics::SourceString;
+
+/// The extension allowing file names in `#line` directives.
+pub const SHADING_LANGUAGE_INCLUDE_EXTENSION: &str = "GL_ARB_shading_language_include";
+
+/// How the `#line` directives refer to the included files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineDirectives {
+    /// `#line 1 2`, which every driver understands. The driver's log then refers to the files by
+    /// number, see `PreprocessedSource::sources`.
+    Numbered,
+    /// `#line 1 "common/position.glsl"`, so the driver's log names the files itself. Needs the
+    /// GL_ARB_shading_language_include extension, which the output requires right after
+    /// `#version`.
+    Named,
+}
+
+/// The `#define`s injected into a shader, sorted by name so equal sets compare equal whatever order
+/// they were added in.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Defines(BTreeMap<String, String>);
+
+impl Defines {
+    pub fn new() -> Defines {
+        Defines::default()
+    }
+
+    /// Add `#define name`, for the shader to test with `#ifdef name`.
+    pub fn with(self, name: &str) -> Defines {
+        self.with_value(name, "")
+    }
+
+    /// Add `#define name value`.
+    pub fn with_value(mut self, name: &str, value: &str) -> Defines {
+        self.0.insert(String::from(name), String::from(value));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The names and values of the defines, sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+}
+
+/// A shader with its includes resolved, ready for the driver.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PreprocessedSource {
+    pub code: String,
+    /// The source strings of the `#line` directives: the shader itself is 0, and each included
+    /// file gets the next number, in the order they were included.
+    pub sources: Vec<SourceString>,
+    /// The paths of the included files (directly or not), without duplicates.
+    pub includes: Vec<String>,
+}
+
+/// Resolve the `#include` directives of a shader, and inject its defines.
+///
+/// # Arguments
+/// * `name` - Name of the shader for the `#line` directives and error messages
+/// * `code` - The GLSL code of the shader
+/// * `defines` - The `#define`s to insert after the `#version` line
+/// * `line_directives` - How the `#line` directives refer to the files
+/// * `read_include` - Returns the content of a file from its path relative to src/shaders
+pub fn preprocess(name: &str,
+                  code: &str,
+                  defines: &Defines,
+                  line_directives: LineDirectives,
+                  read_include: &mut dyn FnMut(&str) -> Result<String, String>) -> Result<PreprocessedSource, String> {
+    for (define_name, value) in defines.iter() {
+        let is_identifier = define_name.starts_with(|character: char| character.is_ascii_alphabetic() || character == '_')
+            && define_name.chars().all(|character| character.is_ascii_alphanumeric() || character == '_');
+        if !is_identifier {
+            return Err(format!("{} is not a valid name for a define", define_name));
+        }
+        if value.contains('\n') {
+            return Err(format!("The value of the define {} is on several lines", define_name));
+        }
+    }
+
+    let mut preprocessor = Preprocessor {
+        defines,
+        line_directives,
+        read_include,
+        output: String::with_capacity(code.len()),
+        sources: vec![SourceString { name: String::from(name), code: String::from(code) }],
+        includes: Vec::new(),
+        include_stack: vec![String::from(name)],
+    };
+
+    // Without a `#version` line, there is nothing the prologue must come after
+    if !code.lines().any(is_version_directive) {
+        preprocessor.prologue(1);
+    }
+    preprocessor.expand(name, code, 0)?;
+
+    Ok(PreprocessedSource {
+        code: preprocessor.output,
+        sources: preprocessor.sources,
+        includes: preprocessor.includes,
+    })
+}
+
+struct Preprocessor<'a> {
+    defines: &'a Defines,
+    line_directives: LineDirectives,
+    read_include: &'a mut dyn FnMut(&str) -> Result<String, String>,
+    output: String,
+    sources: Vec<SourceString>,
+    includes: Vec<String>,
+    /// The files being expanded, from the shader itself to the innermost include.
+    include_stack: Vec<String>,
+}
+
+impl Preprocessor<'_> {
+    fn expand(&mut self, name: &str, code: &str, source: usize) -> Result<(), String> {
+        for (index, line) in code.lines().enumerate() {
+            let line_number = index + 1;
+            let path = match parse_include(line) {
+                Some(Ok(path)) => path,
+                Some(Err(message)) => return Err(format!("{}:{}: {}", name, line_number, message)),
+                None => {
+                    self.output.push_str(line);
+                    self.output.push('\n');
+                    if source == 0 && is_version_directive(line) {
+                        self.prologue(line_number + 1);
+                    }
+                    continue;
+                }
+            };
+
+            if self.include_stack.iter().any(|included| included == path) {
+                return Err(format!("{}:{}: Include cycle: {} -> {}",
+                                   name, line_number, self.include_stack.join(" -> "), path));
+            }
+            let included_code = (self.read_include)(path)
+                .map_err(|error| format!("{}:{}: Could not include {}: {}", name, line_number, path, error))?;
+            if included_code.lines().any(is_version_directive) {
+                return Err(format!("{}:{}: {} has a #version line, but included files can't have one",
+                                   name, line_number, path));
+            }
+
+            let included_source = self.sources.len();
+            self.sources.push(SourceString { name: String::from(path), code: included_code.clone() });
+            if !self.includes.iter().any(|include| include == path) {
+                self.includes.push(String::from(path));
+            }
+
+            self.line_directive(1, included_source);
+            self.include_stack.push(String::from(path));
+            self.expand(path, &included_code, included_source)?;
+            self.include_stack.pop();
+            // Back to the line after the `#include`
+            self.line_directive(line_number + 1, source);
+        }
+        Ok(())
+    }
+
+    /// What goes right after `#version`: the extension of named `#line` directives and the
+    /// defines. The next line is then the line `next_line` of the shader itself.
+    fn prologue(&mut self, next_line: usize) {
+        if self.line_directives == LineDirectives::Numbered && self.defines.is_empty() {
+            return;
+        }
+        if self.line_directives == LineDirectives::Named {
+            writeln!(self.output, "#extension {} : require", SHADING_LANGUAGE_INCLUDE_EXTENSION).unwrap();
+        }
+        for (name, value) in self.defines.iter() {
+            if value.is_empty() {
+                writeln!(self.output, "#define {}", name).unwrap();
+            } else {
+                writeln!(self.output, "#define {} {}", name, value).unwrap();
+            }
+        }
+        self.line_directive(next_line, 0);
+    }
+
+    /// Make the next line the line `line` of the source `source`.
+    fn line_directive(&mut self, line: usize, source: usize) {
+        match self.line_directives {
+            LineDirectives::Numbered => writeln!(self.output, "#line {} {}", line, source),
+            // The name is a string literal, which can't contain double quotes
+            LineDirectives::Named =>
+                writeln!(self.output, "#line {} \"{}\"", line, self.sources[source].name.replace('"', "'")),
+        }.unwrap();
+    }
+}
+
+/// The path of an `#include "path"` line, or None for any other line.
+fn parse_include(line: &str) -> Option<Result<&str, String>> {
+    let directive = line.trim_start().strip_prefix('#')?.trim_start().strip_prefix("include")?;
+    // Some other directive, such as `#included`
+    if !directive.starts_with(|character: char| character.is_whitespace() || character == '"') {
+        return None;
+    }
+
+    let Some(quoted) = directive.trim_start().strip_prefix('"') else {
+        return Some(Err(String::from("#include expects a path between double quotes")));
+    };
+    let Some((path, rest)) = quoted.split_once('"') else {
+        return Some(Err(String::from("Missing the closing double quote of the #include path")));
+    };
+    let rest = rest.trim();
+    if !rest.is_empty() && !rest.starts_with("//") {
+        return Some(Err(format!("Unexpected {} after the #include path", rest)));
+    }
+    if path.is_empty() {
+        return Some(Err(String::from("The #include path is empty")));
+    }
+    Some(Ok(path))
+}
+
+fn is_version_directive(line: &str) -> bool {
+    line.trim_start().strip_prefix('#')
+        .is_some_and(|directive| directive.trim_start().starts_with("version"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::*;
+
+    fn preprocess_with(files: &[(&str, &str)], code: &str, line_directives: LineDirectives) -> Result<PreprocessedSource, String> {
+        preprocess_with_defines(files, code, &Defines::new(), line_directives)
+    }
+
+    fn preprocess_with_defines(files: &[(&str, &str)],
+                               code: &str,
+                               defines: &Defines,
+                               line_directives: LineDirectives) -> Result<PreprocessedSource, String> {
+        let files: HashMap<&str, &str> = files.iter().copied().collect();
+        preprocess("main.vert", code, defines, line_directives, &mut |path| {
+            files.get(path).map(|code| String::from(*code)).ok_or_else(|| String::from("No such file"))
+        })
+    }
+
+    #[test]
+    fn includes_are_surrounded_by_line_directives() {
+        let files = [
+            ("common/a.glsl", "// a\n#include \"common/b.glsl\"\nfloat a;\n"),
+            ("common/b.glsl", "float b;"),
+        ];
+        let source = preprocess_with(&files, "#version 330 core\n#include \"common/a.glsl\"\nvoid main() {}\n",
+                                     LineDirectives::Numbered).unwrap();
+
+        assert_eq!(source.code, "#version 330 core\n\
+            #line 1 1\n\
+            // a\n\
+            #line 1 2\n\
+            float b;\n\
+            #line 3 1\n\
+            float a;\n\
+            #line 3 0\n\
+            void main() {}\n");
+        let source_names: Vec<&str> = source.sources.iter().map(|source| source.name.as_str()).collect();
+        assert_eq!(source_names, ["main.vert", "common/a.glsl", "common/b.glsl"]);
+        assert_eq!(source.includes, ["common/a.glsl", "common/b.glsl"]);
+    }
+
+    #[test]
+    fn named_line_directives_require_the_extension() {
+        let files = [("common/a.glsl", "float a;\n")];
+        let source = preprocess_with(&files, "#version 330 core\n\n#include \"common/a.glsl\" // a\n",
+                                     LineDirectives::Named).unwrap();
+
+        assert_eq!(source.code, "#version 330 core\n\
+            #extension GL_ARB_shading_language_include : require\n\
+            #line 2 \"main.vert\"\n\
+            \n\
+            #line 1 \"common/a.glsl\"\n\
+            float a;\n\
+            #line 4 \"main.vert\"\n");
+    }
+
+    #[test]
+    fn defines_are_injected_after_the_version() {
+        let defines = Defines::new().with("YELLOW").with_value("COUNT", "4");
+        let source = preprocess_with_defines(&[], "// Comment\n#version 330 core\nvoid main() {}\n", &defines,
+                                             LineDirectives::Numbered).unwrap();
+
+        assert_eq!(source.code, "// Comment\n\
+            #version 330 core\n\
+            #define COUNT 4\n\
+            #define YELLOW\n\
+            #line 3 0\n\
+            void main() {}\n");
+        assert_eq!(defines, Defines::new().with_value("COUNT", "4").with("YELLOW"));
+
+        let error = preprocess_with_defines(&[], "", &Defines::new().with("2D"), LineDirectives::Numbered).unwrap_err();
+        assert_eq!(error, "2D is not a valid name for a define");
+    }
+
+    #[test]
+    fn include_cycles_are_errors() {
+        let files = [
+            ("common/a.glsl", "#include \"common/b.glsl\"\n"),
+            ("common/b.glsl", "\n#include \"common/a.glsl\"\n"),
+        ];
+        let error = preprocess_with(&files, "#version 330 core\n#include \"common/a.glsl\"\n",
+                                    LineDirectives::Numbered).unwrap_err();
+
+        assert_eq!(error, "common/b.glsl:2: Include cycle: main.vert -> common/a.glsl -> common/b.glsl -> common/a.glsl");
+    }
+
+    #[test]
+    fn missing_and_malformed_includes_are_errors() {
+        let files = [("common/version.glsl", "#version 330 core\n")];
+        let preprocess_main = |code: &str| preprocess_with(&files, code, LineDirectives::Numbered).unwrap_err();
+
+        assert_eq!(preprocess_main("#include \"common/missing.glsl\""),
+                   "main.vert:1: Could not include common/missing.glsl: No such file");
+        assert_eq!(preprocess_main("\n#include common/a.glsl"),
+                   "main.vert:2: #include expects a path between double quotes");
+        assert_eq!(preprocess_main("#include \"common/version.glsl\""),
+                   "main.vert:1: common/version.glsl has a #version line, but included files can't have one");
+    }
+}
@@ -0,0 +1,534 @@
+/*
+Program reflection: what a linked program expects from the CPU side.
+
+The driver knows every active uniform and attribute of a program, with its GLSL type, so instead of
+looking their locations up by hand (where a typo silently returns -1) the program lists them once
+after linking, see `ShaderProgram::reflection` and `ShaderProgram::set_uniform`.
+
+Only the variables the shaders actually use are active: a uniform that is declared but never read is
+optimized out, and has no location.
+
+Uniform blocks are listed too, with the offset of each of their members in the buffer backing them,
+which is what uniform_buffer.rs checks the Rust structs against.
+ */
+
+/// A uniform or an attribute of a linked program.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ActiveVariable {
+    /// The name as reported by the driver. Arrays are named after their first element, such as
+    /// "lights[0]".
+    pub name: String,
+    /// The GL type, such as gl::FLOAT_VEC4, see `glsl_type_name`.
+    pub gl_type: u32,
+    /// Number of elements of an array, 1 otherwise.
+    pub size: i32,
+    /// Location to pass to gl::Uniform* or gl::VertexAttribPointer. -1 for the built-in attributes
+    /// such as gl_VertexID.
+    pub location: i32,
+}
+
+impl ActiveVariable {
+    /// Whether this is the variable called `name`, arrays being called by their name alone too.
+    fn is_named(&self, name: &str) -> bool {
+        self.name == name || self.name.strip_suffix("[0]") == Some(name)
+    }
+}
+
+/// A member of a uniform block, as laid out in the buffer backing the block.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockMember {
+    /// The name as reported by the driver, which is prefixed with the name of the block when the
+    /// block has an instance name, such as "Lighting.color".
+    pub name: String,
+    pub gl_type: u32,
+    /// Number of elements of an array, 1 otherwise.
+    pub size: i32,
+    /// In bytes, from the start of the block.
+    pub offset: usize,
+    /// Bytes between two elements of an array, 0 for anything else.
+    pub array_stride: usize,
+    /// Bytes between two columns of a matrix, or two rows when `row_major`, 0 for anything else.
+    pub matrix_stride: usize,
+    /// Whether the matrix is stored row by row, from `layout (row_major)`. False for anything
+    /// that is not a matrix.
+    pub row_major: bool,
+}
+
+/// An active uniform block of a linked program.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ActiveBlock {
+    pub name: String,
+    /// Index of the block in the program, for gl::UniformBlockBinding.
+    pub index: u32,
+    /// Minimum size of the buffer backing the block, in bytes.
+    pub data_size: usize,
+    /// Sorted by offset.
+    pub members: Vec<BlockMember>,
+}
+
+/// The active uniforms and attributes of a linked program.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProgramReflection {
+    /// The uniforms of the default block. The members of uniform blocks are not in there, since
+    /// they are set through their buffer instead, see `blocks`.
+    pub uniforms: Vec<ActiveVariable>,
+    pub attributes: Vec<ActiveVariable>,
+    pub blocks: Vec<ActiveBlock>,
+}
+
+impl ProgramReflection {
+    /// List the active uniforms and attributes of a linked program.
+    pub fn new(program: u32) -> ProgramReflection {
+        unsafe {
+            let mut uniforms = active_variables(program, gl::ACTIVE_UNIFORMS, gl::ACTIVE_UNIFORM_MAX_LENGTH,
+                                                gl::GetActiveUniform, gl::GetUniformLocation);
+            uniforms.retain(|uniform| uniform.location != -1);
+            ProgramReflection {
+                uniforms,
+                attributes: active_variables(program, gl::ACTIVE_ATTRIBUTES, gl::ACTIVE_ATTRIBUTE_MAX_LENGTH,
+                                             gl::GetActiveAttrib, gl::GetAttribLocation),
+                blocks: active_blocks(program),
+            }
+        }
+    }
+
+    pub fn uniform(&self, name: &str) -> Option<&ActiveVariable> {
+        self.uniforms.iter().find(|uniform| uniform.is_named(name))
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&ActiveVariable> {
+        self.attributes.iter().find(|attribute| attribute.is_named(name))
+    }
+
+    pub fn block(&self, name: &str) -> Option<&ActiveBlock> {
+        self.blocks.iter().find(|block| block.name == name)
+    }
+}
+
+/// The active uniforms or attributes of a program, sorted by name.
+///
+/// # Arguments
+/// * `program` - A linked program
+/// * `count_parameter` - gl::ACTIVE_UNIFORMS or gl::ACTIVE_ATTRIBUTES
+/// * `max_length_parameter` - gl::ACTIVE_UNIFORM_MAX_LENGTH or gl::ACTIVE_ATTRIBUTE_MAX_LENGTH
+/// * `get_active` - gl::GetActiveUniform or gl::GetActiveAttrib
+/// * `get_location` - gl::GetUniformLocation or gl::GetAttribLocation
+unsafe fn active_variables(program: u32,
+                           count_parameter: u32,
+                           max_length_parameter: u32,
+                           get_active: unsafe fn(u32, u32, i32, *mut i32, *mut i32, *mut u32, *mut gl::types::GLchar),
+                           get_location: unsafe fn(u32, *const gl::types::GLchar) -> i32) -> Vec<ActiveVariable> {
+    let mut count = 0;
+    gl::GetProgramiv(program, count_parameter, &mut count);
+    // The length includes the terminating NUL
+    let mut max_length = 0;
+    gl::GetProgramiv(program, max_length_parameter, &mut max_length);
+
+    let mut variables: Vec<ActiveVariable> = (0..count.max(0) as u32).map(|index| {
+        let mut name = vec![0u8; max_length.max(1) as usize];
+        let mut length = 0;
+        let mut size = 0;
+        let mut gl_type = 0;
+        get_active(program, index, name.len() as i32, &mut length, &mut size, &mut gl_type,
+                   name.as_mut_ptr() as *mut gl::types::GLchar);
+
+        // Keep the NUL for get_location
+        name.truncate(length.max(0) as usize + 1);
+        let location = get_location(program, name.as_ptr() as *const gl::types::GLchar);
+        name.pop();
+
+        ActiveVariable { name: String::from_utf8_lossy(&name).into_owned(), gl_type, size, location }
+    }).collect();
+
+    variables.sort_by(|first, second| first.name.cmp(&second.name));
+    variables
+}
+
+/// The active uniform blocks of a program, sorted by name.
+unsafe fn active_blocks(program: u32) -> Vec<ActiveBlock> {
+    let mut count = 0;
+    gl::GetProgramiv(program, gl::ACTIVE_UNIFORM_BLOCKS, &mut count);
+    let mut max_name_length = 0;
+    gl::GetProgramiv(program, gl::ACTIVE_UNIFORM_BLOCK_MAX_NAME_LENGTH, &mut max_name_length);
+    let mut max_member_name_length = 0;
+    gl::GetProgramiv(program, gl::ACTIVE_UNIFORM_MAX_LENGTH, &mut max_member_name_length);
+
+    let mut blocks: Vec<ActiveBlock> = (0..count.max(0) as u32).map(|index| {
+        let block_parameter = |parameter: u32| {
+            let mut value = 0;
+            gl::GetActiveUniformBlockiv(program, index, parameter, &mut value);
+            value
+        };
+
+        let mut name = vec![0u8; max_name_length.max(1) as usize];
+        let mut length = 0;
+        gl::GetActiveUniformBlockName(program, index, name.len() as i32, &mut length,
+                                      name.as_mut_ptr() as *mut gl::types::GLchar);
+        name.truncate(length.max(0) as usize);
+
+        let mut member_indices = vec![0i32; block_parameter(gl::UNIFORM_BLOCK_ACTIVE_UNIFORMS).max(0) as usize];
+        gl::GetActiveUniformBlockiv(program, index, gl::UNIFORM_BLOCK_ACTIVE_UNIFORM_INDICES, member_indices.as_mut_ptr());
+        let member_indices: Vec<u32> = member_indices.into_iter().map(|member_index| member_index as u32).collect();
+        // One value per member
+        let member_parameter = |parameter: u32| {
+            let mut values = vec![0i32; member_indices.len()];
+            gl::GetActiveUniformsiv(program, member_indices.len() as i32, member_indices.as_ptr(), parameter,
+                                    values.as_mut_ptr());
+            values
+        };
+        let (types, sizes) = (member_parameter(gl::UNIFORM_TYPE), member_parameter(gl::UNIFORM_SIZE));
+        let offsets = member_parameter(gl::UNIFORM_OFFSET);
+        let array_strides = member_parameter(gl::UNIFORM_ARRAY_STRIDE);
+        let matrix_strides = member_parameter(gl::UNIFORM_MATRIX_STRIDE);
+        let row_majors = member_parameter(gl::UNIFORM_IS_ROW_MAJOR);
+
+        let mut members: Vec<BlockMember> = member_indices.iter().enumerate().map(|(member, member_index)| {
+            let mut member_name = vec![0u8; max_member_name_length.max(1) as usize];
+            let mut length = 0;
+            gl::GetActiveUniformName(program, *member_index, member_name.len() as i32, &mut length,
+                                     member_name.as_mut_ptr() as *mut gl::types::GLchar);
+            member_name.truncate(length.max(0) as usize);
+
+            BlockMember {
+                name: String::from_utf8_lossy(&member_name).into_owned(),
+                gl_type: types[member] as u32,
+                size: sizes[member],
+                offset: offsets[member] as usize,
+                array_stride: array_strides[member] as usize,
+                matrix_stride: matrix_strides[member] as usize,
+                row_major: row_majors[member] != 0,
+            }
+        }).collect();
+        members.sort_by_key(|member| member.offset);
+
+        ActiveBlock {
+            name: String::from_utf8_lossy(&name).into_owned(),
+            index,
+            data_size: block_parameter(gl::UNIFORM_BLOCK_DATA_SIZE) as usize,
+            members,
+        }
+    }).collect();
+
+    blocks.sort_by(|first, second| first.name.cmp(&second.name));
+    blocks
+}
+
+/// The GLSL name of a GL type, such as "vec4" for gl::FLOAT_VEC4.
+pub fn glsl_type_name(gl_type: u32) -> &'static str {
+    match gl_type {
+        gl::FLOAT => "float",
+        gl::FLOAT_VEC2 => "vec2",
+        gl::FLOAT_VEC3 => "vec3",
+        gl::FLOAT_VEC4 => "vec4",
+        gl::INT => "int",
+        gl::INT_VEC2 => "ivec2",
+        gl::INT_VEC3 => "ivec3",
+        gl::INT_VEC4 => "ivec4",
+        gl::UNSIGNED_INT => "uint",
+        gl::UNSIGNED_INT_VEC2 => "uvec2",
+        gl::UNSIGNED_INT_VEC3 => "uvec3",
+        gl::UNSIGNED_INT_VEC4 => "uvec4",
+        gl::BOOL => "bool",
+        gl::BOOL_VEC2 => "bvec2",
+        gl::BOOL_VEC3 => "bvec3",
+        gl::BOOL_VEC4 => "bvec4",
+        gl::FLOAT_MAT2 => "mat2",
+        gl::FLOAT_MAT3 => "mat3",
+        gl::FLOAT_MAT4 => "mat4",
+        gl::FLOAT_MAT2x3 => "mat2x3",
+        gl::FLOAT_MAT2x4 => "mat2x4",
+        gl::FLOAT_MAT3x2 => "mat3x2",
+        gl::FLOAT_MAT3x4 => "mat3x4",
+        gl::FLOAT_MAT4x2 => "mat4x2",
+        gl::FLOAT_MAT4x3 => "mat4x3",
+        gl::SAMPLER_1D => "sampler1D",
+        gl::SAMPLER_2D => "sampler2D",
+        gl::SAMPLER_3D => "sampler3D",
+        gl::SAMPLER_CUBE => "samplerCube",
+        gl::SAMPLER_2D_RECT => "sampler2DRect",
+        gl::SAMPLER_1D_ARRAY => "sampler1DArray",
+        gl::SAMPLER_2D_ARRAY => "sampler2DArray",
+        gl::SAMPLER_CUBE_MAP_ARRAY => "samplerCubeArray",
+        gl::SAMPLER_2D_MULTISAMPLE => "sampler2DMS",
+        gl::SAMPLER_2D_MULTISAMPLE_ARRAY => "sampler2DMSArray",
+        gl::SAMPLER_BUFFER => "samplerBuffer",
+        gl::SAMPLER_1D_SHADOW => "sampler1DShadow",
+        gl::SAMPLER_2D_SHADOW => "sampler2DShadow",
+        gl::SAMPLER_CUBE_SHADOW => "samplerCubeShadow",
+        gl::SAMPLER_2D_RECT_SHADOW => "sampler2DRectShadow",
+        gl::SAMPLER_1D_ARRAY_SHADOW => "sampler1DArrayShadow",
+        gl::SAMPLER_2D_ARRAY_SHADOW => "sampler2DArrayShadow",
+        gl::SAMPLER_CUBE_MAP_ARRAY_SHADOW => "samplerCubeArrayShadow",
+        gl::INT_SAMPLER_1D => "isampler1D",
+        gl::INT_SAMPLER_2D => "isampler2D",
+        gl::INT_SAMPLER_3D => "isampler3D",
+        gl::INT_SAMPLER_CUBE => "isamplerCube",
+        gl::INT_SAMPLER_2D_RECT => "isampler2DRect",
+        gl::INT_SAMPLER_1D_ARRAY => "isampler1DArray",
+        gl::INT_SAMPLER_2D_ARRAY => "isampler2DArray",
+        gl::INT_SAMPLER_CUBE_MAP_ARRAY => "isamplerCubeArray",
+        gl::INT_SAMPLER_2D_MULTISAMPLE => "isampler2DMS",
+        gl::INT_SAMPLER_2D_MULTISAMPLE_ARRAY => "isampler2DMSArray",
+        gl::INT_SAMPLER_BUFFER => "isamplerBuffer",
+        gl::UNSIGNED_INT_SAMPLER_1D => "usampler1D",
+        gl::UNSIGNED_INT_SAMPLER_2D => "usampler2D",
+        gl::UNSIGNED_INT_SAMPLER_3D => "usampler3D",
+        gl::UNSIGNED_INT_SAMPLER_CUBE => "usamplerCube",
+        gl::UNSIGNED_INT_SAMPLER_2D_RECT => "usampler2DRect",
+        gl::UNSIGNED_INT_SAMPLER_1D_ARRAY => "usampler1DArray",
+        gl::UNSIGNED_INT_SAMPLER_2D_ARRAY => "usampler2DArray",
+        gl::UNSIGNED_INT_SAMPLER_CUBE_MAP_ARRAY => "usamplerCubeArray",
+        gl::UNSIGNED_INT_SAMPLER_2D_MULTISAMPLE => "usampler2DMS",
+        gl::UNSIGNED_INT_SAMPLER_2D_MULTISAMPLE_ARRAY => "usampler2DMSArray",
+        gl::UNSIGNED_INT_SAMPLER_BUFFER => "usamplerBuffer",
+        _ => "unknown type",
+    }
+}
+
+/// The texture unit a sampler uniform reads from, such as `Sampler(0)` for gl::TEXTURE0.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sampler(pub u32);
+
+/// A Rust value that can be given to a uniform, see `ShaderProgram::set_uniform`.
+pub trait UniformValue {
+    /// The GL types of the uniforms this value can be given to.
+    const GL_TYPES: &'static [u32];
+
+    /// Set the uniform at `location` of the program in use.
+    ///
+    /// # Safety
+    /// A program must be in use, and `location` be one of its uniforms of a type in `GL_TYPES`.
+    unsafe fn set(&self, location: i32);
+}
+
+impl UniformValue for f32 {
+    const GL_TYPES: &'static [u32] = &[gl::FLOAT];
+    unsafe fn set(&self, location: i32) { gl::Uniform1f(location, *self); }
+}
+
+impl UniformValue for [f32; 2] {
+    const GL_TYPES: &'static [u32] = &[gl::FLOAT_VEC2];
+    unsafe fn set(&self, location: i32) { gl::Uniform2fv(location, 1, self.as_ptr()); }
+}
+
+impl UniformValue for [f32; 3] {
+    const GL_TYPES: &'static [u32] = &[gl::FLOAT_VEC3];
+    unsafe fn set(&self, location: i32) { gl::Uniform3fv(location, 1, self.as_ptr()); }
+}
+
+impl UniformValue for [f32; 4] {
+    const GL_TYPES: &'static [u32] = &[gl::FLOAT_VEC4];
+    unsafe fn set(&self, location: i32) { gl::Uniform4fv(location, 1, self.as_ptr()); }
+}
+
+impl UniformValue for i32 {
+    const GL_TYPES: &'static [u32] = &[gl::INT];
+    unsafe fn set(&self, location: i32) { gl::Uniform1i(location, *self); }
+}
+
+impl UniformValue for [i32; 2] {
+    const GL_TYPES: &'static [u32] = &[gl::INT_VEC2];
+    unsafe fn set(&self, location: i32) { gl::Uniform2iv(location, 1, self.as_ptr()); }
+}
+
+impl UniformValue for [i32; 3] {
+    const GL_TYPES: &'static [u32] = &[gl::INT_VEC3];
+    unsafe fn set(&self, location: i32) { gl::Uniform3iv(location, 1, self.as_ptr()); }
+}
+
+impl UniformValue for [i32; 4] {
+    const GL_TYPES: &'static [u32] = &[gl::INT_VEC4];
+    unsafe fn set(&self, location: i32) { gl::Uniform4iv(location, 1, self.as_ptr()); }
+}
+
+impl UniformValue for u32 {
+    const GL_TYPES: &'static [u32] = &[gl::UNSIGNED_INT];
+    unsafe fn set(&self, location: i32) { gl::Uniform1ui(location, *self); }
+}
+
+impl UniformValue for [u32; 2] {
+    const GL_TYPES: &'static [u32] = &[gl::UNSIGNED_INT_VEC2];
+    unsafe fn set(&self, location: i32) { gl::Uniform2uiv(location, 1, self.as_ptr()); }
+}
+
+impl UniformValue for [u32; 3] {
+    const GL_TYPES: &'static [u32] = &[gl::UNSIGNED_INT_VEC3];
+    unsafe fn set(&self, location: i32) { gl::Uniform3uiv(location, 1, self.as_ptr()); }
+}
+
+impl UniformValue for [u32; 4] {
+    const GL_TYPES: &'static [u32] = &[gl::UNSIGNED_INT_VEC4];
+    unsafe fn set(&self, location: i32) { gl::Uniform4uiv(location, 1, self.as_ptr()); }
+}
+
+impl UniformValue for bool {
+    const GL_TYPES: &'static [u32] = &[gl::BOOL];
+    unsafe fn set(&self, location: i32) { gl::Uniform1i(location, *self as i32); }
+}
+
+// Matrices are given column by column, as GLSL stores them, so a matCxR is C columns of R floats
+
+impl UniformValue for [[f32; 2]; 2] {
+    const GL_TYPES: &'static [u32] = &[gl::FLOAT_MAT2];
+    unsafe fn set(&self, location: i32) { gl::UniformMatrix2fv(location, 1, gl::FALSE, self.as_ptr() as *const f32); }
+}
+
+impl UniformValue for [[f32; 3]; 3] {
+    const GL_TYPES: &'static [u32] = &[gl::FLOAT_MAT3];
+    unsafe fn set(&self, location: i32) { gl::UniformMatrix3fv(location, 1, gl::FALSE, self.as_ptr() as *const f32); }
+}
+
+impl UniformValue for [[f32; 4]; 4] {
+    const GL_TYPES: &'static [u32] = &[gl::FLOAT_MAT4];
+    unsafe fn set(&self, location: i32) { gl::UniformMatrix4fv(location, 1, gl::FALSE, self.as_ptr() as *const f32); }
+}
+
+impl UniformValue for [[f32; 3]; 2] {
+    const GL_TYPES: &'static [u32] = &[gl::FLOAT_MAT2x3];
+    unsafe fn set(&self, location: i32) { gl::UniformMatrix2x3fv(location, 1, gl::FALSE, self.as_ptr() as *const f32); }
+}
+
+impl UniformValue for [[f32; 4]; 2] {
+    const GL_TYPES: &'static [u32] = &[gl::FLOAT_MAT2x4];
+    unsafe fn set(&self, location: i32) { gl::UniformMatrix2x4fv(location, 1, gl::FALSE, self.as_ptr() as *const f32); }
+}
+
+impl UniformValue for [[f32; 2]; 3] {
+    const GL_TYPES: &'static [u32] = &[gl::FLOAT_MAT3x2];
+    unsafe fn set(&self, location: i32) { gl::UniformMatrix3x2fv(location, 1, gl::FALSE, self.as_ptr() as *const f32); }
+}
+
+impl UniformValue for [[f32; 4]; 3] {
+    const GL_TYPES: &'static [u32] = &[gl::FLOAT_MAT3x4];
+    unsafe fn set(&self, location: i32) { gl::UniformMatrix3x4fv(location, 1, gl::FALSE, self.as_ptr() as *const f32); }
+}
+
+impl UniformValue for [[f32; 2]; 4] {
+    const GL_TYPES: &'static [u32] = &[gl::FLOAT_MAT4x2];
+    unsafe fn set(&self, location: i32) { gl::UniformMatrix4x2fv(location, 1, gl::FALSE, self.as_ptr() as *const f32); }
+}
+
+impl UniformValue for [[f32; 3]; 4] {
+    const GL_TYPES: &'static [u32] = &[gl::FLOAT_MAT4x3];
+    unsafe fn set(&self, location: i32) { gl::UniformMatrix4x3fv(location, 1, gl::FALSE, self.as_ptr() as *const f32); }
+}
+
+impl UniformValue for Sampler {
+    const GL_TYPES: &'static [u32] = &[
+        gl::SAMPLER_1D, gl::SAMPLER_2D, gl::SAMPLER_3D, gl::SAMPLER_CUBE, gl::SAMPLER_2D_RECT,
+        gl::SAMPLER_1D_ARRAY, gl::SAMPLER_2D_ARRAY, gl::SAMPLER_CUBE_MAP_ARRAY,
+        gl::SAMPLER_2D_MULTISAMPLE, gl::SAMPLER_2D_MULTISAMPLE_ARRAY, gl::SAMPLER_BUFFER,
+        gl::SAMPLER_1D_SHADOW, gl::SAMPLER_2D_SHADOW, gl::SAMPLER_CUBE_SHADOW, gl::SAMPLER_2D_RECT_SHADOW,
+        gl::SAMPLER_1D_ARRAY_SHADOW, gl::SAMPLER_2D_ARRAY_SHADOW, gl::SAMPLER_CUBE_MAP_ARRAY_SHADOW,
+        gl::INT_SAMPLER_1D, gl::INT_SAMPLER_2D, gl::INT_SAMPLER_3D, gl::INT_SAMPLER_CUBE,
+        gl::INT_SAMPLER_2D_RECT, gl::INT_SAMPLER_1D_ARRAY, gl::INT_SAMPLER_2D_ARRAY,
+        gl::INT_SAMPLER_CUBE_MAP_ARRAY, gl::INT_SAMPLER_2D_MULTISAMPLE,
+        gl::INT_SAMPLER_2D_MULTISAMPLE_ARRAY, gl::INT_SAMPLER_BUFFER,
+        gl::UNSIGNED_INT_SAMPLER_1D, gl::UNSIGNED_INT_SAMPLER_2D, gl::UNSIGNED_INT_SAMPLER_3D,
+        gl::UNSIGNED_INT_SAMPLER_CUBE, gl::UNSIGNED_INT_SAMPLER_2D_RECT, gl::UNSIGNED_INT_SAMPLER_1D_ARRAY,
+        gl::UNSIGNED_INT_SAMPLER_2D_ARRAY, gl::UNSIGNED_INT_SAMPLER_CUBE_MAP_ARRAY,
+        gl::UNSIGNED_INT_SAMPLER_2D_MULTISAMPLE, gl::UNSIGNED_INT_SAMPLER_2D_MULTISAMPLE_ARRAY,
+        gl::UNSIGNED_INT_SAMPLER_BUFFER,
+    ];
+    unsafe fn set(&self, location: i32) { gl::Uniform1i(location, self.0 as i32); }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use crate::context_config::ContextConfig;
+    use crate::headless::HeadlessContext;
+    use crate::shader::{ShaderProgram, ShaderSource, ShaderStage};
+    use super::*;
+
+    #[test]
+    fn active_uniforms_and_attributes_are_listed() {
+        let _context = HeadlessContext::new(&ContextConfig::default(), 1, 1).unwrap();
+
+        let program = ShaderProgram::new(&[
+            ShaderSource::new("reflected.vert", "#version 330 core\n\
+                layout (location = 2) in vec3 aPosition;\n\
+                uniform mat4 transform;\n\
+                uniform float unused;\n\
+                void main() { gl_Position = transform * vec4(aPosition, 1.0); }\n", ShaderStage::Vertex),
+            ShaderSource::new("reflected.frag", "#version 330 core\n\
+                out vec4 FragColor;\n\
+                uniform sampler2D image;\n\
+                uniform vec4 tints[3];\n\
+                void main() { FragColor = texture(image, vec2(0.5)) * tints[2]; }\n", ShaderStage::Fragment),
+        ]).unwrap();
+        let reflection = program.reflection();
+
+        let uniforms: Vec<(&str, &str, i32)> = reflection.uniforms.iter()
+            .map(|uniform| (uniform.name.as_str(), glsl_type_name(uniform.gl_type), uniform.size))
+            .collect();
+        assert_eq!(uniforms, [("image", "sampler2D", 1), ("tints[0]", "vec4", 3), ("transform", "mat4", 1)]);
+        assert_eq!(reflection.uniform("tints").map(|uniform| uniform.name.as_str()), Some("tints[0]"));
+        assert!(reflection.uniform("unused").is_none());
+
+        let position = reflection.attribute("aPosition").unwrap();
+        assert_eq!((position.gl_type, position.location), (gl::FLOAT_VEC3, 2));
+    }
+
+    #[test]
+    fn every_sampler_type_has_a_name() {
+        for &gl_type in Sampler::GL_TYPES {
+            assert_ne!(glsl_type_name(gl_type), "unknown type", "{:#x}", gl_type);
+        }
+    }
+
+    #[test]
+    fn unsigned_vectors_non_square_matrices_and_samplers_are_set() {
+        let _context = HeadlessContext::new(&ContextConfig::default(), 1, 1).unwrap();
+
+        let program = ShaderProgram::new(&[
+            ShaderSource::new("typed.vert", "#version 330 core\n\
+                uniform mat2x3 shear;\n\
+                uniform mat4x2 project;\n\
+                void main() { gl_Position = vec4(shear * vec2(1.0), 1.0) + vec4(project * vec4(1.0), 0.0, 0.0); }\n",
+                              ShaderStage::Vertex),
+            ShaderSource::new("typed.frag", "#version 330 core\n\
+                out vec4 FragColor;\n\
+                uniform uvec3 counts;\n\
+                uniform isampler3D volume;\n\
+                uniform usamplerBuffer indices;\n\
+                uniform sampler2DArrayShadow shadows;\n\
+                void main() {\n\
+                    FragColor = vec4(counts, 1.0) + vec4(texture(volume, vec3(0.5))) + vec4(texelFetch(indices, 0))\n\
+                        + vec4(texture(shadows, vec4(0.5)));\n\
+                }\n", ShaderStage::Fragment),
+        ]).unwrap();
+
+        let reflection = program.reflection();
+        let types: Vec<(&str, &str)> = reflection.uniforms.iter()
+            .map(|uniform| (uniform.name.as_str(), glsl_type_name(uniform.gl_type)))
+            .collect();
+        assert_eq!(types, [("counts", "uvec3"), ("indices", "usamplerBuffer"), ("project", "mat4x2"),
+                           ("shadows", "sampler2DArrayShadow"), ("shear", "mat2x3"), ("volume", "isampler3D")]);
+
+        program.use_program();
+        program.set_uniform("counts", [1u32, 2, 3]);
+        program.set_uniform("shear", [[1.0f32, 2.0, 3.0], [4.0, 5.0, 6.0]]);
+        program.set_uniform("project", [[1.0f32, 2.0], [3.0, 4.0], [5.0, 6.0], [7.0, 8.0]]);
+        program.set_uniform("volume", Sampler(1));
+        program.set_uniform("indices", Sampler(2));
+        program.set_uniform("shadows", Sampler(3));
+
+        let location = |name: &str| program.reflection().uniform(name).unwrap().location;
+        let mut counts = [0u32; 3];
+        let mut shear = [0.0f32; 6];
+        let mut project = [0.0f32; 8];
+        let mut units = [0i32; 3];
+        unsafe {
+            gl::GetUniformuiv(program.id(), location("counts"), counts.as_mut_ptr());
+            gl::GetUniformfv(program.id(), location("shear"), shear.as_mut_ptr());
+            gl::GetUniformfv(program.id(), location("project"), project.as_mut_ptr());
+            for (unit, name) in units.iter_mut().zip(["volume", "indices", "shadows"]) {
+                gl::GetUniformiv(program.id(), location(name), unit);
+            }
+        }
+        assert_eq!(counts, [1, 2, 3]);
+        assert_eq!(shear, [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(project, [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0]);
+        assert_eq!(units, [1, 2, 3]);
+    }
+}
@@ -0,0 +1,26 @@
+/*
+A scene is a single exercise that can be drawn by the host loop in main.rs.
+
+The host owns the loop: it calls `setup` once, `frame` once per frame, and `teardown` before
+switching to another scene, so each scene only has to care about its own GL objects.
+ */
+
+use crate::frame_clock::FrameTime;
+use crate::shader::ShaderError;
+
+pub trait Scene {
+    /// Create all the GL objects (VAOs, VBOs, programs, etc...) the scene needs.
+    ///
+    /// Fails when one of its shaders doesn't compile or link, or needs a newer context. The host
+    /// still calls `teardown` afterwards, so whatever was created before the error is deleted.
+    fn setup(&mut self) -> Result<(), ShaderError>;
+
+    /// Draw a single frame. The host swaps the window buffers afterwards.
+    ///
+    /// # Arguments
+    /// * `time` - Timing of this frame, shared by everything drawn in it
+    fn frame(&mut self, time: &FrameTime);
+
+    /// Delete every GL object created in `setup`.
+    fn teardown(&mut self);
+}
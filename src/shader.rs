@@ -0,0 +1,889 @@
+/*
+Compiling shaders and linking them into programs.
+
+`ShaderProgram` does the whole sequence (create, attach, link, then detach and delete the shaders
+that are no longer needed) and deletes the program when it is dropped, so a scene only has to keep
+it around for as long as it draws with it.
+
+Both steps can fail on perfectly valid Rust code, since the GLSL is only checked by the driver at
+runtime. Instead of panicking, the errors carry everything needed to find the problem: which
+source failed, at which stage, and the driver's complete log, also parsed into diagnostics that
+point to the offending lines (see diagnostics.rs).
+
+The shaders of src/shaders are referred to by their name in the library of shader_library.rs.
+Release builds embed them in the binary, while debug builds read them from disk so they can be
+edited while the exercises run, see hot_reload.rs. Every source goes through preprocessor.rs first,
+so shaders can include the files of `INCLUDE_FILES`, and be compiled with different defines.
+`ProgramCache` keeps the programs built for every combination of shaders and defines, so switching
+between them costs a lookup.
+ */
+
+use std::any::type_name;
+use std::borrow::Cow;
+use std::cell::{Cell, Ref, RefCell};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::ffi::{c_char, CStr, CString};
+use std::fmt;
+use std::rc::Rc;
+#[cfg(debug_assertions)]
+use std::rc::Weak;
+use crate::binary_cache;
+use crate::diagnostics::{parse_log, Diagnostic};
+use crate::preprocessor::{preprocess, Defines, LineDirectives, PreprocessedSource, SHADING_LANGUAGE_INCLUDE_EXTENSION};
+use crate::reflection::{glsl_type_name, ProgramReflection, UniformValue};
+use crate::shader_library::{self, ShaderFile, INCLUDE_FILES};
+
+pub use crate::shader_library::ShaderStage;
+
+impl ShaderStage {
+    /// The matching shader type for `gl::CreateShader`, such as gl::VERTEX_SHADER.
+    pub fn gl_type(self) -> u32 {
+        match self {
+            ShaderStage::Vertex => gl::VERTEX_SHADER,
+            ShaderStage::Geometry => gl::GEOMETRY_SHADER,
+            ShaderStage::Fragment => gl::FRAGMENT_SHADER,
+            ShaderStage::Compute => gl::COMPUTE_SHADER,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ShaderError {
+    /// A single shader didn't compile.
+    Compilation {
+        stage: ShaderStage,
+        /// Name of the source, usually its file name such as "first_shader.vert".
+        source_name: String,
+        /// The driver's log, as is.
+        log: String,
+        /// The messages of the log, located in the source and the files it included.
+        diagnostics: Vec<Diagnostic>,
+    },
+    /// The current context is too old for the stage, such as compute shaders before OpenGL 4.3.
+    UnsupportedStage {
+        stage: ShaderStage,
+        source_name: String,
+        /// Version of the current context.
+        context_version: (u8, u8),
+    },
+    /// An `#include` of the source could not be resolved, or one of its defines is invalid, see
+    /// preprocessor.rs.
+    Preprocessing {
+        source_name: String,
+        message: String,
+    },
+    /// No shader of the library has that name, see shader_library.rs.
+    UnknownShader {
+        name: String,
+    },
+    /// The shaders are not linked together by any entry of `shader_library::PROGRAMS`, so build.rs
+    /// doesn't check them.
+    UnregisteredProgram {
+        /// The names of the shaders, such as "basic/position + color/orange".
+        names: String,
+    },
+    /// All the shaders compiled, but could not be linked together.
+    Linkage {
+        /// Name of the program, usually the names of its sources.
+        program_name: String,
+        log: String,
+    },
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderError::Compilation { stage, source_name, log, diagnostics } => {
+                write!(f, "Compilation of the {} shader {} failed:", stage, source_name)?;
+                if diagnostics.is_empty() {
+                    return write!(f, "\n{}", log);
+                }
+                for diagnostic in diagnostics {
+                    write!(f, "\n{}", diagnostic)?;
+                }
+                Ok(())
+            }
+            ShaderError::UnsupportedStage { stage, source_name, context_version } => {
+                let (major, minor) = stage.required_version();
+                write!(f, "The {} shader {} needs OpenGL {}.{}, but the context is only {}.{}",
+                       stage, source_name, major, minor, context_version.0, context_version.1)
+            }
+            ShaderError::Preprocessing { source_name, message } =>
+                write!(f, "Preprocessing of the shader {} failed: {}", source_name, message),
+            ShaderError::UnknownShader { name } =>
+                write!(f, "There is no shader called {} in the shader library, run with --list-shaders to see all of them", name),
+            ShaderError::UnregisteredProgram { names } =>
+                write!(f, "The program {} is not in shader_library::PROGRAMS, add it there so build.rs checks it", names),
+            ShaderError::Linkage { program_name, log } =>
+                write!(f, "Linkage of the shader program {} failed:\n{}", program_name, log),
+        }
+    }
+}
+
+impl ShaderError {
+    /// The diagnostics of a compilation error, for tools that present them their own way. Empty
+    /// for the other errors.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        match self {
+            ShaderError::Compilation { diagnostics, .. } => diagnostics,
+            _ => &[],
+        }
+    }
+}
+
+impl Error for ShaderError {}
+
+/// The GLSL code of one stage of a program.
+#[derive(Clone, Debug)]
+pub struct ShaderSource<'a> {
+    /// Name of the source for error messages, usually its file name.
+    pub name: &'a str,
+    pub code: Cow<'a, str>,
+    pub stage: ShaderStage,
+    /// The file the code was read from, which makes the program reloadable.
+    pub file: Option<ShaderFile>,
+    /// Inserted after the `#version` line of the code.
+    pub defines: Defines,
+}
+
+impl<'a> ShaderSource<'a> {
+    pub fn new(name: &'a str, code: &'a str, stage: ShaderStage) -> ShaderSource<'a> {
+        ShaderSource { name, code: Cow::Borrowed(code), stage, file: None, defines: Defines::new() }
+    }
+
+    /// The current content of a file of src/shaders, see `ShaderFile::code`.
+    pub fn file(file: ShaderFile, stage: ShaderStage) -> ShaderSource<'static> {
+        ShaderSource { name: file.file_name(), code: file.code(), stage, file: Some(file), defines: Defines::new() }
+    }
+
+    /// The current content of a shader of the library, with its defines.
+    ///
+    /// # Arguments
+    /// * `name` - Name of the shader in the library, such as "color/yellow"
+    pub fn library(name: &str) -> Result<ShaderSource<'static>, ShaderError> {
+        let unknown_shader = || ShaderError::UnknownShader { name: String::from(name) };
+        let library_shader = shader_library::shader(name).ok_or_else(unknown_shader)?;
+        let stage = ShaderStage::of_file(&library_shader.file).ok_or_else(unknown_shader)?;
+        let defines = library_shader.defines.iter().fold(Defines::new(), |defines, define| defines.with(define));
+        Ok(ShaderSource::file(library_shader.file, stage).with_defines(defines))
+    }
+
+    /// The same source, compiled with `defines`.
+    pub fn with_defines(self, defines: Defines) -> ShaderSource<'a> {
+        ShaderSource { defines, ..self }
+    }
+}
+
+/// A linked shader program, deleted when dropped.
+///
+/// Like every GL object it must be dropped while its context is still current.
+///
+/// In debug builds, a program built from files only is rebuilt when they change, see
+/// hot_reload.rs. `id` is then different after the host polled the changes.
+#[derive(Debug)]
+pub struct ShaderProgram {
+    /// Shared with the list of live programs in debug builds, which rebuilds it in place.
+    linked: Rc<LinkedProgram>,
+    /// The uniforms `set_uniform` already warned about.
+    warned_uniforms: RefCell<HashSet<String>>,
+}
+
+/// What a reload replaces of a `ShaderProgram`.
+#[derive(Debug)]
+struct LinkedProgram {
+    id: Cell<u32>,
+    name: String,
+    /// The active uniforms and attributes, listed once after linking.
+    reflection: RefCell<ProgramReflection>,
+    /// The binding point of each uniform block, set again on the reloaded programs.
+    #[cfg(debug_assertions)]
+    block_bindings: RefCell<Vec<(String, u32)>>,
+    /// The files, stages and defines the program was built from, if it can be reloaded.
+    #[cfg(debug_assertions)]
+    files: Option<Vec<(ShaderFile, ShaderStage, Defines)>>,
+    /// The files the sources included when the program was last built, which also trigger a
+    /// reload when they change.
+    #[cfg(debug_assertions)]
+    includes: RefCell<Vec<String>>,
+}
+
+#[cfg(debug_assertions)]
+thread_local! {
+    /// The programs of the thread that can be reloaded, so the host can rebuild them between
+    /// frames, see `reload_programs`.
+    static RELOADABLE_PROGRAMS: RefCell<Vec<Weak<LinkedProgram>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Rebuild the live programs built from one of `changed_paths`, called by the host between
+/// frames through `ShaderWatcher::poll`. A program whose new version doesn't build keeps its
+/// previous version, and the error is printed.
+///
+/// # Arguments
+/// * `changed_paths` - Paths of the changed files, relative to src/shaders
+#[cfg(debug_assertions)]
+pub(crate) fn reload_programs(changed_paths: &[String]) {
+    let programs: Vec<Rc<LinkedProgram>> = RELOADABLE_PROGRAMS.with_borrow_mut(|programs| {
+        programs.retain(|program| program.strong_count() > 0);
+        programs.iter().filter_map(Weak::upgrade).collect()
+    });
+    for program in programs {
+        let Some(files) = &program.files else {
+            continue;
+        };
+        let changed = files.iter().map(|(file, _, _)| file.path)
+            .chain(program.includes.borrow().iter().map(String::as_str))
+            .any(|path| changed_paths.iter().any(|changed_path| changed_path == path));
+        if changed {
+            program.reload(files);
+        }
+    }
+}
+
+#[cfg(debug_assertions)]
+impl LinkedProgram {
+    fn reload(&self, files: &[(ShaderFile, ShaderStage, Defines)]) {
+        let sources: Vec<ShaderSource> = files.iter()
+            .map(|(file, stage, defines)| ShaderSource::file(*file, *stage).with_defines(defines.clone()))
+            .collect();
+        match link_program(&sources, &self.name) {
+            Ok((id, includes)) => {
+                unsafe { gl::DeleteProgram(self.id.get()); }
+                self.id.set(id);
+                self.includes.replace(includes);
+                self.reflection.replace(ProgramReflection::new(id));
+                for (block_name, binding) in self.block_bindings.borrow().iter() {
+                    self.apply_block_binding(block_name, *binding);
+                }
+                println!("Reloaded {}", self.name);
+            }
+            Err(error) => eprintln!("Could not reload {}, keeping the previous version. {}", self.name, error),
+        }
+    }
+}
+
+impl LinkedProgram {
+    fn apply_block_binding(&self, block_name: &str, binding: u32) {
+        if let Some(block) = self.reflection.borrow().block(block_name) {
+            unsafe { gl::UniformBlockBinding(self.id.get(), block.index, binding); }
+        }
+    }
+}
+
+impl ShaderProgram {
+    /// Compile every stage and link them into a program.
+    ///
+    /// # Arguments
+    /// * `sources` - One source per stage: a vertex and a fragment shader, and optionally a
+    ///   geometry shader. Or a compute shader alone, see `ComputeProgram`
+    pub fn new(sources: &[ShaderSource]) -> Result<ShaderProgram, ShaderError> {
+        let name = sources.iter().map(|source| source.name).collect::<Vec<_>>().join(" + ");
+        #[cfg_attr(not(debug_assertions), allow(unused_variables))]
+        let (id, includes) = link_program(sources, &name)?;
+
+        let linked = Rc::new(LinkedProgram {
+            id: Cell::new(id),
+            name,
+            reflection: RefCell::new(ProgramReflection::new(id)),
+            #[cfg(debug_assertions)]
+            block_bindings: RefCell::new(Vec::new()),
+            #[cfg(debug_assertions)]
+            files: sources.iter().map(|source| Some((source.file?, source.stage, source.defines.clone()))).collect(),
+            #[cfg(debug_assertions)]
+            includes: RefCell::new(includes),
+        });
+        #[cfg(debug_assertions)]
+        if linked.files.is_some() {
+            RELOADABLE_PROGRAMS.with_borrow_mut(|programs| programs.push(Rc::downgrade(&linked)));
+        }
+
+        Ok(ShaderProgram {
+            linked,
+            warned_uniforms: RefCell::new(HashSet::new()),
+        })
+    }
+
+    /// Build a program from shaders of the library.
+    ///
+    /// # Arguments
+    /// * `names` - The name of the shader of each stage, such as `["basic/position", "color/orange"]`,
+    ///   which must be an entry of `shader_library::PROGRAMS`
+    pub fn from_library(names: &[&str]) -> Result<ShaderProgram, ShaderError> {
+        ShaderProgram::new(&library_sources(names)?)
+    }
+
+    /// Draw with this program from now on.
+    pub fn use_program(&self) {
+        unsafe { gl::UseProgram(self.id()); }
+    }
+
+    /// The GL handle of the program, for the calls this type doesn't wrap.
+    pub fn id(&self) -> u32 {
+        self.linked.id.get()
+    }
+
+    /// The active uniforms and attributes of the program.
+    pub fn reflection(&self) -> Ref<'_, ProgramReflection> {
+        self.linked.reflection.borrow()
+    }
+
+    /// Set a uniform of the program, which must be in use (see `use_program`).
+    ///
+    /// A uniform that is not active, or whose GLSL type doesn't match the value, is left alone and
+    /// a warning is printed, only the first time.
+    ///
+    /// # Arguments
+    /// * `name` - Name of the uniform in the shaders, such as "customColor"
+    /// * `value` - A value of the matching type, such as `[f32; 4]` for a vec4, `[[f32; 4]; 4]`
+    ///   (column by column) for a mat4, or `Sampler` for a sampler2D
+    pub fn set_uniform<T: UniformValue>(&self, name: &str, value: T) {
+        let reflection = self.linked.reflection.borrow();
+        let warning = match reflection.uniform(name) {
+            Some(uniform) if T::GL_TYPES.contains(&uniform.gl_type) => {
+                unsafe { value.set(uniform.location); }
+                return;
+            }
+            Some(uniform) => format!("The uniform {} of {} is a {}, it can't be set with a {}",
+                                     name, self.name(), glsl_type_name(uniform.gl_type), type_name::<T>()),
+            None => format!("{} has no active uniform {}, it is either misspelled or unused by the shaders",
+                            self.name(), name),
+        };
+        if self.warned_uniforms.borrow_mut().insert(String::from(name)) {
+            eprintln!("Warning: {}", warning);
+        }
+    }
+
+    /// Make a uniform block of the program read the buffer bound to `binding`, usually through
+    /// `UniformBuffer::attach`, which also checks the layout of the block. Blocks that are not
+    /// active are ignored.
+    pub fn bind_uniform_block(&self, block_name: &str, binding: u32) {
+        self.linked.apply_block_binding(block_name, binding);
+        #[cfg(debug_assertions)]
+        {
+            let mut block_bindings = self.linked.block_bindings.borrow_mut();
+            block_bindings.retain(|(name, _)| name != block_name);
+            block_bindings.push((String::from(block_name), binding));
+        }
+    }
+
+    /// The names of the sources the program was built from, such as "a.vert + a.frag".
+    pub fn name(&self) -> &str {
+        &self.linked.name
+    }
+}
+
+impl Drop for ShaderProgram {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteProgram(self.id()); }
+    }
+}
+
+/// The sources of the shaders of the library called `names`, which must be linked together by an
+/// entry of `shader_library::PROGRAMS`.
+fn library_sources(names: &[&str]) -> Result<Vec<ShaderSource<'static>>, ShaderError> {
+    let sources = names.iter().map(|name| ShaderSource::library(name)).collect::<Result<Vec<_>, _>>()?;
+    if !shader_library::is_program(names) {
+        return Err(ShaderError::UnregisteredProgram { names: names.join(" + ") });
+    }
+    Ok(sources)
+}
+
+/// The sources of a program of the library, with `defines` added to the defines of each shader.
+fn defined_library_sources(names: &[&str], defines: &Defines) -> Result<Vec<ShaderSource<'static>>, ShaderError> {
+    Ok(library_sources(names)?.into_iter().map(|source| {
+        let defines = defines.iter().fold(source.defines.clone(), |all, (name, value)| all.with_value(name, value));
+        source.with_defines(defines)
+    }).collect())
+}
+
+/// The programs built from shaders of the library, one for every combination of shaders and
+/// defines asked for.
+///
+/// The programs are only built the first time they are asked for, see `ProgramCache::get`, and
+/// deleted when the cache is dropped or cleared.
+#[derive(Debug, Default)]
+pub struct ProgramCache {
+    programs: HashMap<ProgramKey, ShaderProgram>,
+}
+
+/// The names of the shaders of a program, and its defines.
+type ProgramKey = (Vec<String>, Defines);
+
+impl ProgramCache {
+    pub fn new() -> ProgramCache {
+        ProgramCache::default()
+    }
+
+    /// The program built from the shaders called `names` with `defines`, which is built now if it
+    /// wasn't already.
+    ///
+    /// # Arguments
+    /// * `names` - The name of the shader of each stage, as for `ShaderProgram::from_library`
+    /// * `defines` - The `#define`s of every stage, on top of the shaders' own
+    pub fn get(&mut self, names: &[&str], defines: &Defines) -> Result<&ShaderProgram, ShaderError> {
+        let key = (names.iter().map(|name| String::from(*name)).collect(), defines.clone());
+        let program = match self.programs.entry(key) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                entry.insert(ShaderProgram::new(&defined_library_sources(names, defines)?)?)
+            }
+        };
+        Ok(program)
+    }
+
+    /// Number of programs built so far.
+    pub fn len(&self) -> usize {
+        self.programs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.programs.is_empty()
+    }
+
+    /// Delete every program, which will be built again when asked for.
+    pub fn clear(&mut self) {
+        self.programs.clear();
+    }
+}
+
+/// Compile every stage, link them into a new program and return its handle, along with the files
+/// the sources included.
+///
+/// When the program binary cache is enabled, the program is loaded from it if it is there, and saved
+/// into it otherwise, see binary_cache.rs.
+fn link_program(sources: &[ShaderSource], name: &str) -> Result<(u32, Vec<String>), ShaderError> {
+    let preprocessed = sources.iter()
+        .map(|source| preprocess_shader(source.name, &source.code, source.stage, &source.defines))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut includes: Vec<String> = preprocessed.iter().flat_map(|source| source.includes.iter().cloned()).collect();
+    includes.sort();
+    includes.dedup();
+
+    let binary_cache = binary_cache::enabled_cache()
+        .map(|cache| (cache, binary_cache::program_key(sources, &preprocessed)));
+    if let Some(program) = binary_cache.as_ref().and_then(|(cache, key)| cache.load(*key)) {
+        return Ok((program, includes));
+    }
+
+    let mut shaders = Vec::with_capacity(sources.len());
+    for (source, preprocessed) in sources.iter().zip(&preprocessed) {
+        match compile_preprocessed(source.name, source.stage, preprocessed) {
+            Ok(shader) => shaders.push(shader),
+            Err(error) => {
+                unsafe { shaders.iter().for_each(|shader| gl::DeleteShader(*shader)); }
+                return Err(error);
+            }
+        }
+    }
+
+    let program = unsafe {
+        let program = gl::CreateProgram();
+        shaders.iter().for_each(|shader| gl::AttachShader(program, *shader));
+        if binary_cache.is_some() {
+            gl::ProgramParameteri(program, gl::PROGRAM_BINARY_RETRIEVABLE_HINT, gl::TRUE as i32);
+        }
+        gl::LinkProgram(program);
+
+        // Once the program is linked the shaders are not needed anymore, whether the linkage
+        // succeeded or not
+        for shader in &shaders {
+            gl::DetachShader(program, *shader);
+            gl::DeleteShader(*shader);
+        }
+
+        if let Err(error) = validate_shader_program_linkage(program, name) {
+            gl::DeleteProgram(program);
+            return Err(error);
+        }
+        program
+    };
+
+    if let Some((cache, key)) = binary_cache {
+        if let Err(error) = cache.store(key, program) {
+            eprintln!("Warning: could not save the program {} into the cache: {}", name, error);
+        }
+    }
+    Ok((program, includes))
+}
+
+/// Load a shader source code to the GPU, and return the handle to the compiled shader.
+///
+/// The `#include` directives of the code are resolved first, see `INCLUDE_FILES`.
+///
+/// # Arguments
+/// * `source_name` - Name of the source for error messages, such as its file name
+/// * `shader_source_code` - Shader's code in GLSL langauage
+/// * `stage` - Which stage of the pipeline the shader is for
+/// * `defines` - The `#define`s to insert after the `#version` line
+pub fn load_shader(source_name: &str,
+                   shader_source_code: &str,
+                   stage: ShaderStage,
+                   defines: &Defines) -> Result<u32, ShaderError> {
+    compile_shader(source_name, shader_source_code, stage, defines).map(|(shader, _)| shader)
+}
+
+/// `load_shader`, which also returns the paths of the files the code included.
+fn compile_shader(source_name: &str,
+                  shader_source_code: &str,
+                  stage: ShaderStage,
+                  defines: &Defines) -> Result<(u32, Vec<String>), ShaderError> {
+    let preprocessed = preprocess_shader(source_name, shader_source_code, stage, defines)?;
+    let shader = compile_preprocessed(source_name, stage, &preprocessed)?;
+    Ok((shader, preprocessed.includes))
+}
+
+/// Resolve the `#include`s of a source and insert its defines, after making sure the current
+/// context has its stage.
+fn preprocess_shader(source_name: &str,
+                     shader_source_code: &str,
+                     stage: ShaderStage,
+                     defines: &Defines) -> Result<PreprocessedSource, ShaderError> {
+    let context_version = current_context_version();
+    if context_version < stage.required_version() {
+        return Err(ShaderError::UnsupportedStage {
+            stage,
+            source_name: String::from(source_name),
+            context_version,
+        });
+    }
+
+    // Drivers with the extension name the included files in their logs themselves, the others only
+    // number them
+    let line_directives = if has_extension(SHADING_LANGUAGE_INCLUDE_EXTENSION) {
+        LineDirectives::Named
+    } else {
+        LineDirectives::Numbered
+    };
+    preprocess(source_name, shader_source_code, defines, line_directives, &mut read_include)
+        .map_err(|message| ShaderError::Preprocessing { source_name: String::from(source_name), message })
+}
+
+/// Compile a preprocessed source, and return the handle to the compiled shader.
+fn compile_preprocessed(source_name: &str,
+                        stage: ShaderStage,
+                        preprocessed: &PreprocessedSource) -> Result<u32, ShaderError> {
+    let compilation_error = |log: String, diagnostics: Vec<Diagnostic>| ShaderError::Compilation {
+        stage,
+        source_name: String::from(source_name),
+        log,
+        diagnostics,
+    };
+    let shader_source_code_c_string = CString::new(preprocessed.code.as_str())
+        .map_err(|_| compilation_error(String::from("The source contains a NUL byte"), Vec::new()))?;
+
+    unsafe {
+        let shader = gl::CreateShader(stage.gl_type());
+        gl::ShaderSource(
+            shader,
+            1,
+            &shader_source_code_c_string.as_ptr(),
+            std::ptr::null()
+        );
+        gl::CompileShader(shader);
+
+        let mut success: i32 = 0;
+        gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut success);
+        if success == 0 {
+            let log = info_log(shader, gl::GetShaderiv, gl::GetShaderInfoLog);
+            gl::DeleteShader(shader);
+            let diagnostics = parse_log(&log, &preprocessed.sources);
+            return Err(compilation_error(log, diagnostics));
+        }
+
+        Ok(shader)
+    }
+}
+
+/// The content of a file of `INCLUDE_FILES`, from its path relative to src/shaders.
+fn read_include(path: &str) -> Result<String, String> {
+    INCLUDE_FILES.iter()
+        .find(|file| file.path == path)
+        .map(|file| file.code().into_owned())
+        .ok_or_else(|| String::from("The file is not one of shader::INCLUDE_FILES"))
+}
+
+/// Whether the current context supports an OpenGL extension, such as "GL_ARB_shading_language_include".
+fn has_extension(name: &str) -> bool {
+    let mut extension_count = 0;
+    unsafe { gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut extension_count); }
+    (0..extension_count.max(0) as u32).any(|index| {
+        let extension = unsafe { gl::GetStringi(gl::EXTENSIONS, index) };
+        !extension.is_null() && unsafe { CStr::from_ptr(extension as *const c_char) }.to_bytes() == name.as_bytes()
+    })
+}
+
+/// Make sure a program was linked successfully, and return the linker's log otherwise.
+///
+/// # Arguments
+/// * `program` - A program `gl::LinkProgram` was called on
+/// * `program_name` - Name of the program for error messages
+pub fn validate_shader_program_linkage(program: u32, program_name: &str) -> Result<(), ShaderError> {
+    let mut success: i32 = 0;
+
+    unsafe {
+        gl::GetProgramiv(program, gl::LINK_STATUS, &mut success);
+        if success == 0 {
+            return Err(ShaderError::Linkage {
+                program_name: String::from(program_name),
+                log: info_log(program, gl::GetProgramiv, gl::GetProgramInfoLog),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+fn current_context_version() -> (u8, u8) {
+    let mut major_version = 0;
+    let mut minor_version = 0;
+    unsafe {
+        gl::GetIntegerv(gl::MAJOR_VERSION, &mut major_version);
+        gl::GetIntegerv(gl::MINOR_VERSION, &mut minor_version);
+    }
+    (major_version as u8, minor_version as u8)
+}
+
+/// The whole info log of a shader or a program, however long the driver made it.
+///
+/// # Arguments
+/// * `object` - The shader or program
+/// * `get_parameter` - gl::GetShaderiv or gl::GetProgramiv
+/// * `get_info_log` - gl::GetShaderInfoLog or gl::GetProgramInfoLog
+unsafe fn info_log(object: u32,
+                   get_parameter: unsafe fn(u32, u32, *mut i32),
+                   get_info_log: unsafe fn(u32, i32, *mut i32, *mut gl::types::GLchar)) -> String {
+    // The length includes the terminating NUL
+    let mut length: i32 = 0;
+    get_parameter(object, gl::INFO_LOG_LENGTH, &mut length);
+    if length <= 0 {
+        return String::new();
+    }
+
+    let mut log = vec![0u8; length as usize];
+    let mut written: i32 = 0;
+    get_info_log(object, length, &mut written, log.as_mut_ptr() as *mut gl::types::GLchar);
+    log.truncate(written.max(0) as usize);
+
+    String::from_utf8_lossy(&log).trim_end().to_owned()
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use crate::binary_cache::ProgramBinaryCache;
+    use crate::context_config::ContextConfig;
+    use crate::diagnostics::Severity;
+    use crate::headless::HeadlessContext;
+    use super::*;
+
+    #[test]
+    fn compilation_errors_have_the_whole_log() {
+        let _context = HeadlessContext::new(&ContextConfig::default(), 1, 1).unwrap();
+
+        // Enough errors to produce a log much longer than a fixed size buffer would hold
+        let undeclared_variables: String = (0..50)
+            .map(|index| format!("    FragColor += vec4(undeclared_variable_{});\n", index))
+            .collect();
+        let source = format!("#version 330 core\nout vec4 FragColor;\nvoid main() {{\n{}}}\n", undeclared_variables);
+
+        match load_shader("broken.frag", &source, ShaderStage::Fragment, &Defines::new()) {
+            Err(ShaderError::Compilation { stage, source_name, log, .. }) => {
+                assert_eq!(stage, ShaderStage::Fragment);
+                assert_eq!(source_name, "broken.frag");
+                assert!(log.len() > 512, "The log is only {} bytes long", log.len());
+                assert!(log.contains("undeclared_variable_49"), "{}", log);
+                assert!(!log.contains('\0'));
+            }
+            other => panic!("Expected a compilation error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn errors_after_an_include_point_to_the_right_line() {
+        let _context = HeadlessContext::new(&ContextConfig::default(), 1, 1).unwrap();
+
+        let source = "#version 330 core\n#include \"common/position.glsl\"\nvoid main() { gl_Position = undeclared; }\n";
+        match load_shader("broken.vert", source, ShaderStage::Vertex, &Defines::new()) {
+            Err(error @ ShaderError::Compilation { .. }) => {
+                let diagnostic = &error.diagnostics()[0];
+                assert_eq!((diagnostic.severity, diagnostic.file.as_str(), diagnostic.line), (Severity::Error, "broken.vert", Some(3)));
+                assert_eq!(diagnostic.source_line.as_deref(), Some("void main() { gl_Position = undeclared; }"));
+                assert!(error.to_string().contains("3 | void main() { gl_Position = undeclared; }"), "{}", error);
+            }
+            other => panic!("Expected a compilation error, got {:?}", other),
+        }
+
+        let error = load_shader("missing.vert", "#include \"common/missing.glsl\"\n", ShaderStage::Vertex,
+                                &Defines::new()).unwrap_err();
+        assert!(matches!(error, ShaderError::Preprocessing { ref message, .. } if message.contains("common/missing.glsl")), "{}", error);
+    }
+
+    #[test]
+    fn linkage_errors_are_reported() {
+        let _context = HeadlessContext::new(&ContextConfig::default(), 1, 1).unwrap();
+
+        // A fragment shader alone, whose main calls a function no stage defines
+        let fragment_shader = load_shader("undefined_function.frag", "#version 330 core\n\
+            out vec4 FragColor;\nvec4 color();\nvoid main() { FragColor = color(); }\n", ShaderStage::Fragment, &Defines::new())
+            .unwrap();
+
+        unsafe {
+            let program = gl::CreateProgram();
+            gl::AttachShader(program, fragment_shader);
+            gl::LinkProgram(program);
+
+            let error = validate_shader_program_linkage(program, "undefined_function").unwrap_err();
+            assert!(matches!(error, ShaderError::Linkage { ref program_name, .. } if program_name == "undefined_function"));
+            assert!(!error.to_string().contains('\0'));
+
+            gl::DeleteProgram(program);
+            gl::DeleteShader(fragment_shader);
+        }
+    }
+
+    #[test]
+    fn program_cache_builds_each_variant_once() {
+        let _context = HeadlessContext::new(&ContextConfig::default(), 1, 1).unwrap();
+
+        let shaders = ["basic/position", "color/orange"];
+        let mut programs = ProgramCache::new();
+        let orange = programs.get(&shaders, &Defines::new()).unwrap().id();
+        let yellow = programs.get(&shaders, &Defines::new().with("YELLOW")).unwrap().id();
+        assert_ne!(orange, yellow);
+
+        assert_eq!(programs.get(&shaders, &Defines::new()).unwrap().id(), orange);
+        assert_eq!(programs.get(&shaders, &Defines::new().with("YELLOW")).unwrap().id(), yellow);
+        assert_eq!(programs.len(), 2);
+
+        let error = programs.get(&["basic/position", "color/purple"], &Defines::new()).unwrap_err();
+        assert_eq!(error, ShaderError::UnknownShader { name: String::from("color/purple") });
+    }
+
+    #[test]
+    fn only_the_registered_programs_are_built() {
+        let _context = HeadlessContext::new(&ContextConfig::default(), 1, 1).unwrap();
+
+        let unregistered = ShaderError::UnregisteredProgram { names: String::from("basic/position + color/interpolated") };
+        let error = ShaderProgram::from_library(&["basic/position", "color/interpolated"]).unwrap_err();
+        assert_eq!(error, unregistered);
+        let error = ProgramCache::new().get(&["basic/position", "color/interpolated"], &Defines::new()).unwrap_err();
+        assert_eq!(error, unregistered);
+    }
+
+    #[test]
+    fn uniforms_are_only_set_with_the_matching_type() {
+        let _context = HeadlessContext::new(&ContextConfig::default(), 1, 1).unwrap();
+
+        let program = ShaderProgram::new(&[
+            ShaderSource::new("position.vert", "#version 330 core\n\
+                void main() { gl_Position = vec4(0.0); }\n", ShaderStage::Vertex),
+            ShaderSource::new("uniform.frag", "#version 330 core\n\
+                out vec4 FragColor;\nuniform vec4 customColor;\nvoid main() { FragColor = customColor; }\n",
+                              ShaderStage::Fragment),
+        ]).unwrap();
+        program.use_program();
+        let location = program.reflection().uniform("customColor").unwrap().location;
+        let custom_color = || {
+            let mut color = [0.0f32; 4];
+            unsafe { gl::GetUniformfv(program.id(), location, color.as_mut_ptr()); }
+            color
+        };
+
+        program.set_uniform("customColor", [0.1f32, 0.2, 0.3, 0.4]);
+        assert_eq!(custom_color(), [0.1, 0.2, 0.3, 0.4]);
+
+        program.set_uniform("customColor", [1.0f32, 1.0, 1.0]);
+        program.set_uniform("customColour", [1.0f32, 1.0, 1.0, 1.0]);
+        program.set_uniform("customColour", [1.0f32, 1.0, 1.0, 1.0]);
+        assert_eq!(custom_color(), [0.1, 0.2, 0.3, 0.4]);
+        assert_eq!(program.warned_uniforms.borrow().len(), 2);
+    }
+
+    /// The path of the program built from shaders of the library in the cache of `directory`.
+    fn cached_program_path(directory: &std::path::Path, names: &[&str], defines: &Defines) -> std::path::PathBuf {
+        let sources = defined_library_sources(names, defines).unwrap();
+        let preprocessed: Vec<_> = sources.iter()
+            .map(|source| preprocess_shader(source.name, &source.code, source.stage, &source.defines).unwrap())
+            .collect();
+        ProgramBinaryCache::new(directory).path(binary_cache::program_key(&sources, &preprocessed))
+    }
+
+    #[test]
+    fn programs_are_saved_then_loaded_from_the_binary_cache() {
+        use std::os::unix::fs::MetadataExt;
+
+        let _context = HeadlessContext::new(&ContextConfig::default(), 1, 1).unwrap();
+        if !ProgramBinaryCache::is_supported() {
+            eprintln!("The driver can't save programs, skipping");
+            return;
+        }
+        let directory = std::env::temp_dir().join(format!("shader-program-cache-{}", std::process::id()));
+        let names = ["basic/position", "color/uniform"];
+        let path = cached_program_path(&directory, &names, &Defines::new());
+        binary_cache::enable(&directory);
+
+        let first = ShaderProgram::from_library(&names).unwrap();
+        let stored_file = std::fs::metadata(&path).unwrap().ino();
+        // Loaded from the file rather than saved again, which would replace the file
+        let second = ShaderProgram::from_library(&names).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().ino(), stored_file);
+        assert_ne!(second.id(), first.id());
+        assert_eq!(second.reflection().uniform("customColor").map(|uniform| uniform.gl_type), Some(gl::FLOAT_VEC4));
+
+        let defines = Defines::new().with("UNUSED");
+        let defined_path = cached_program_path(&directory, &names, &defines);
+        assert_ne!(defined_path, path);
+        ProgramCache::new().get(&names, &defines).unwrap();
+        assert!(defined_path.exists());
+
+        // The cache is only enabled for this thread, so the tests running alongside never used it
+        binary_cache::disable();
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn changed_files_reload_the_programs_built_from_them() {
+        let _context = HeadlessContext::new(&ContextConfig::default(), 1, 1).unwrap();
+
+        let orange = ShaderProgram::from_library(&["basic/position", "color/orange"]).unwrap();
+        let uniform = ShaderProgram::from_library(&["basic/position", "color/uniform"]).unwrap();
+        let (orange_id, uniform_id) = (orange.id(), uniform.id());
+
+        reload_programs(&[String::from("fragment/first_shader.frag")]);
+        assert_ne!(orange.id(), orange_id);
+        assert_eq!(uniform.id(), uniform_id);
+
+        // Both include the file through basic/position
+        let orange_id = orange.id();
+        reload_programs(&[String::from("common/position.glsl")]);
+        assert_ne!(orange.id(), orange_id);
+        assert_ne!(uniform.id(), uniform_id);
+        // The uniforms are looked up again in the new program
+        let location = uniform.reflection().uniform("customColor").unwrap().location;
+        let mut active_uniforms = 0;
+        unsafe { gl::GetProgramiv(uniform.id(), gl::ACTIVE_UNIFORMS, &mut active_uniforms); }
+        assert!(location >= 0 && active_uniforms == 1);
+
+        drop(orange);
+        reload_programs(&[String::from("fragment/first_shader.frag")]);
+        RELOADABLE_PROGRAMS.with_borrow(|programs| assert_eq!(programs.len(), 1));
+    }
+
+    #[test]
+    fn programs_keep_no_shaders_attached() {
+        let _context = HeadlessContext::new(&ContextConfig::default(), 1, 1).unwrap();
+
+        let program = ShaderProgram::new(&[
+            ShaderSource::new("position.vert", "#version 330 core\n\
+                void main() { gl_Position = vec4(0.0); }\n", ShaderStage::Vertex),
+            ShaderSource::new("black.frag", "#version 330 core\n\
+                out vec4 FragColor;\nvoid main() { FragColor = vec4(0.0); }\n", ShaderStage::Fragment),
+        ]).unwrap();
+        assert_eq!(program.name(), "position.vert + black.frag");
+
+        let mut attached_shaders = -1;
+        unsafe { gl::GetProgramiv(program.id(), gl::ATTACHED_SHADERS, &mut attached_shaders); }
+        assert_eq!(attached_shaders, 0);
+    }
+}
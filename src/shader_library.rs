@@ -0,0 +1,183 @@
+/*
+The shaders of src/shaders, registered under stable names such as "basic/position" or
+"color/yellow".
+
+Programs are built from those names (see `ShaderProgram::from_library`), so the exercises don't
+need to know where the files are, and this module is the one list of shaders for everything else:
+debug builds reload the programs when the files of their shaders change (see hot_reload.rs),
+build.rs validates every shader and the programs of `PROGRAMS` at build time, and `--list-shaders`
+prints them. Programs can only be built from the combinations of shaders listed in `PROGRAMS`, so
+build.rs checks every program the exercises link.
+
+A name can also stand for a variant of a file, compiled with some defines.
+
+build.rs includes this module as is, so it must not depend on anything else of the crate.
+ */
+
+use std::borrow::Cow;
+use std::fmt;
+
+/// Where the shaders are, and what debug builds read them from.
+pub const SHADER_DIRECTORY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders");
+
+/// A `ShaderFile` for a file of src/shaders, such as `shader_file!("vertex/first_shader.vert")`.
+macro_rules! shader_file {
+    ($path:literal) => {
+        $crate::shader_library::ShaderFile {
+            path: $path,
+            embedded: include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders/", $path)),
+        }
+    };
+}
+
+/// A shader file of src/shaders, created with `shader_file!`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ShaderFile {
+    /// Path relative to src/shaders, such as "fragment/first_shader.frag".
+    pub path: &'static str,
+    /// The content of the file when the crate was built.
+    pub embedded: &'static str,
+}
+
+impl ShaderFile {
+    /// The name of the file without its directory, such as "first_shader.frag".
+    pub fn file_name(&self) -> &'static str {
+        self.path.rsplit('/').next().unwrap()
+    }
+
+    /// The current content of the file. Debug builds read it from disk, and only fall back to the
+    /// embedded content when the file can't be read (for example when the binary runs on another
+    /// machine).
+    pub fn code(&self) -> Cow<'static, str> {
+        #[cfg(debug_assertions)]
+        if let Ok(code) = std::fs::read_to_string(format!("{}/{}", SHADER_DIRECTORY, self.path)) {
+            return Cow::Owned(code);
+        }
+        Cow::Borrowed(self.embedded)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ShaderStage {
+    Vertex,
+    /// Optional, between the vertex and the fragment stages.
+    Geometry,
+    Fragment,
+    /// Not part of the drawing pipeline at all, a compute program is made of this stage alone.
+    Compute,
+}
+
+impl ShaderStage {
+    /// The stage of a file of src/shaders, from its extension: .vert, .geom, .frag or .comp.
+    pub fn of_file(file: &ShaderFile) -> Option<ShaderStage> {
+        match file.path.rsplit('.').next()? {
+            "vert" => Some(ShaderStage::Vertex),
+            "geom" => Some(ShaderStage::Geometry),
+            "frag" => Some(ShaderStage::Fragment),
+            "comp" => Some(ShaderStage::Compute),
+            _ => None,
+        }
+    }
+
+    /// The first OpenGL version with this stage.
+    pub fn required_version(self) -> (u8, u8) {
+        match self {
+            ShaderStage::Vertex | ShaderStage::Fragment => (2, 0),
+            ShaderStage::Geometry => (3, 2),
+            ShaderStage::Compute => (4, 3),
+        }
+    }
+}
+
+impl fmt::Display for ShaderStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderStage::Vertex => write!(f, "vertex"),
+            ShaderStage::Geometry => write!(f, "geometry"),
+            ShaderStage::Fragment => write!(f, "fragment"),
+            ShaderStage::Compute => write!(f, "compute"),
+        }
+    }
+}
+
+/// A shader of the library.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LibraryShader {
+    /// Such as "basic/position", see `shader`.
+    pub name: &'static str,
+    /// The stage is given by the extension of the file: .vert, .geom, .frag or .comp.
+    pub file: ShaderFile,
+    /// Names of the `#define`s the file is compiled with.
+    pub defines: &'static [&'static str],
+}
+
+/// Every shader of the exercises, by name.
+pub const SHADERS: &[LibraryShader] = &[
+    LibraryShader { name: "basic/position", file: shader_file!("vertex/first_shader.vert"), defines: &[] },
+    LibraryShader { name: "basic/position_color", file: shader_file!("vertex/shader_with_color.vert"), defines: &[] },
+    LibraryShader { name: "basic/point_color", file: shader_file!("vertex/point_with_color.vert"), defines: &[] },
+    LibraryShader { name: "color/orange", file: shader_file!("fragment/first_shader.frag"), defines: &[] },
+    LibraryShader { name: "color/yellow", file: shader_file!("fragment/first_shader.frag"), defines: &["YELLOW"] },
+    LibraryShader { name: "color/uniform", file: shader_file!("fragment/first_uniform_shader.frag"), defines: &[] },
+    LibraryShader { name: "color/interpolated", file: shader_file!("fragment/shader_with_color_input.frag"), defines: &[] },
+    LibraryShader { name: "geometry/house", file: shader_file!("geometry/house.geom"), defines: &[] },
+    LibraryShader { name: "particles/update", file: shader_file!("compute/particles.comp"), defines: &[] },
+    LibraryShader { name: "particles/point", file: shader_file!("vertex/particle.vert"), defines: &[] },
+];
+
+/// The files shaders can `#include`. Release builds can't read src/shaders, so the included files
+/// must be embedded like any other shader file.
+pub const INCLUDE_FILES: &[ShaderFile] = &[
+    shader_file!("common/position.glsl"),
+];
+
+/// The shaders the exercises link together, the only programs `ShaderProgram::from_library` builds.
+/// build.rs checks that each stage gets the inputs it expects from the previous one.
+pub const PROGRAMS: &[&[&str]] = &[
+    &["basic/position", "color/orange"],
+    &["basic/position", "color/yellow"],
+    &["basic/position", "color/uniform"],
+    &["basic/position_color", "color/interpolated"],
+    &["basic/point_color", "geometry/house", "color/interpolated"],
+    &["particles/point", "color/interpolated"],
+    &["particles/update"],
+];
+
+/// The shader registered under `name`, if any.
+pub fn shader(name: &str) -> Option<&'static LibraryShader> {
+    SHADERS.iter().find(|shader| shader.name == name)
+}
+
+/// Whether `names` is an entry of `PROGRAMS`.
+pub fn is_program(names: &[&str]) -> bool {
+    PROGRAMS.contains(&names)
+}
+
+/// The names of the shaders made from a file, such as `["color/orange", "color/yellow"]`.
+///
+/// # Arguments
+/// * `path` - Path of the file relative to src/shaders
+pub fn shaders_of_file(path: &str) -> Vec<&'static str> {
+    SHADERS.iter().filter(|shader| shader.file.path == path).map(|shader| shader.name).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_unique_and_programs_are_registered() {
+        for (index, library_shader) in SHADERS.iter().enumerate() {
+            assert!(SHADERS[..index].iter().all(|other| other.name != library_shader.name),
+                    "{} is registered twice", library_shader.name);
+        }
+        for name in PROGRAMS.iter().flat_map(|program| program.iter()) {
+            assert!(shader(name).is_some(), "{} is not a shader of the library", name);
+        }
+        assert_eq!(shader("color/yellow").unwrap().file.path, "fragment/first_shader.frag");
+        assert_eq!(shader("color/purple"), None);
+        assert_eq!(shaders_of_file("fragment/first_shader.frag"), ["color/orange", "color/yellow"]);
+        assert!(is_program(&["basic/position", "color/orange"]));
+        assert!(!is_program(&["basic/position"]));
+    }
+}
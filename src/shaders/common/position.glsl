@@ -0,0 +1,6 @@
+//Input is received from the vertex buffer object
+layout (location=0) in vec3 aPosition;
+
+vec4 vertexPosition() {
+    return vec4(aPosition.x, aPosition.y, aPosition.z, 1.0f);
+}
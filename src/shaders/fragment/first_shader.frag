@@ -3,5 +3,9 @@
 out vec4 FragColor;
 
 void main() {
+#ifdef YELLOW
+    FragColor = vec4(1.0f, 1.0f, 0.0f, 1.0f);
+#else
     FragColor = vec4(1.0f, 0.5f, 0.2f, 1.0f);
+#endif
 }
\ No newline at end of file
@@ -0,0 +1,16 @@
+#version 330 core
+
+//Input is received from the particles storage buffer
+layout (location=0) in vec2 aPosition;
+layout (location=1) in vec2 aVelocity;
+
+out vec3 vertexColor;
+
+void main() {
+    gl_Position = vec4(aPosition.x, aPosition.y, 0.0f, 1.0f);
+    gl_PointSize = 2.0f;
+
+    // Slow particles are blue, fast ones orange
+    float speed = clamp(length(aVelocity), 0.0f, 1.0f);
+    vertexColor = mix(vec3(0.2f, 0.5f, 1.0f), vec3(1.0f, 0.5f, 0.2f), speed);
+}
@@ -0,0 +1,16 @@
+#version 330 core
+
+//Input is received from the vertex buffer object
+layout (location=0) in vec2 aPosition;
+layout (location=1) in vec3 customColor;
+
+// An interface block groups the outputs, the geometry shader receives an array of them (one per
+// vertex of the primitive)
+out VertexData {
+    vec3 color;
+} vertexData;
+
+void main() {
+    gl_Position = vec4(aPosition.x, aPosition.y, 0.0f, 1.0f);
+    vertexData.color = customColor;
+}
@@ -1,12 +1,11 @@
 #version 330 core
 
-//Input is received from the vertex buffer object
-layout (location=0) in vec3 aPosition;
+#include "common/position.glsl"
 layout (location=1) in vec3 customColor;
 
 out vec3 vertexColor;
 
 void main() {
-    gl_Position = vec4(aPosition.x, aPosition.y, aPosition.z, 1.0f);
+    gl_Position = vertexPosition();
     vertexColor = customColor;
 }
\ No newline at end of file
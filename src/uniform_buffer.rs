@@ -0,0 +1,402 @@
+/*
+Uniform buffer objects (UBOs): uniforms that live in a buffer instead of in each program.
+
+A uniform block groups uniforms in the shaders,
+
+    layout (std140) uniform Lighting {
+        vec3 color;
+        float intensity;
+        vec4 ambient;
+    };
+
+and is backed by a buffer bound to a numbered binding point. Every program whose block is attached
+to that binding point reads the same buffer, so setting the uniforms once is enough for all of them.
+
+With the std140 layout the offsets of the members follow fixed rules, which a `#[repr(C)]` struct
+can match, but only with care: vec3 and vec4 are aligned to 16 bytes, and so is every element of an
+array and every column of a matrix, so a `float weights[4]` is a `[[f32; 4]; 4]` and a mat3 a
+`[[f32; 4]; 3]`. A mismatch silently shifts every value after it, so the struct is checked against
+the offsets and strides the driver reports when it is attached to a program (see
+`UniformBuffer::attach`).
+ */
+
+use std::ffi::c_void;
+use std::marker::PhantomData;
+use std::mem::size_of;
+use bytemuck::Pod;
+use crate::reflection::{ActiveBlock, BlockMember};
+use crate::shader::ShaderProgram;
+
+/// A field of a `UniformBlock` struct.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockField {
+    /// The name of the matching member in the shaders.
+    pub name: &'static str,
+    /// Offset of the field in the Rust struct, in bytes.
+    pub offset: usize,
+    /// Size of the field in the Rust struct, in bytes.
+    pub size: usize,
+}
+
+/// A `#[repr(C)]` struct laid out like a std140 uniform block, usually implemented with
+/// `uniform_block!`.
+pub trait UniformBlock: Pod {
+    /// The fields matching the members of the block.
+    const FIELDS: &'static [BlockField];
+}
+
+/// Implement `UniformBlock` for a struct, from the fields matching the members of the block, such
+/// as `uniform_block!(Lighting { color, intensity, ambient_color as "ambientColor" })`. Fields are
+/// named like the members of the block, or renamed with `as`.
+#[macro_export]
+macro_rules! uniform_block {
+    ($block:ty { $($field:ident $(as $name:literal)?),* $(,)? }) => {
+        impl $crate::uniform_buffer::UniformBlock for $block {
+            const FIELDS: &'static [$crate::uniform_buffer::BlockField] = &[
+                $($crate::uniform_buffer::BlockField {
+                    // The name after `as` if there is one, the name of the field otherwise
+                    name: $crate::uniform_buffer::last_name(&[stringify!($field) $(, $name)?]),
+                    offset: std::mem::offset_of!($block, $field),
+                    size: $crate::uniform_buffer::field_size(|block: &$block| &block.$field),
+                }),*
+            ];
+        }
+    };
+}
+#[doc(hidden)]
+pub const fn last_name(names: &[&'static str]) -> &'static str {
+    names[names.len() - 1]
+}
+#[doc(hidden)]
+pub const fn field_size<B, F>(_field: fn(&B) -> &F) -> usize {
+    size_of::<F>()
+}
+
+/// A uniform buffer holding a `T`, bound to a binding point, and deleted when dropped.
+pub struct UniformBuffer<T: UniformBlock> {
+    buffer: u32,
+    binding: u32,
+    block_type: PhantomData<T>,
+}
+
+impl<T: UniformBlock> UniformBuffer<T> {
+    /// Create a buffer holding a copy of `value`, and bind it to a binding point.
+    ///
+    /// # Arguments
+    /// * `value` - The initial content of the block
+    /// * `binding` - The binding point, which the blocks of programs are attached to with `attach`
+    pub fn new(value: &T, binding: u32) -> UniformBuffer<T> {
+        let mut buffer = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut buffer);
+            gl::BindBuffer(gl::UNIFORM_BUFFER, buffer);
+            gl::BufferData(gl::UNIFORM_BUFFER, size_of::<T>() as isize,
+                           value as *const T as *const c_void, gl::DYNAMIC_DRAW);
+            gl::BindBufferBase(gl::UNIFORM_BUFFER, binding, buffer);
+        }
+
+        UniformBuffer {
+            buffer,
+            binding,
+            block_type: PhantomData,
+        }
+    }
+
+    /// Replace the content of the buffer, for every program reading it.
+    pub fn write(&self, value: &T) {
+        unsafe {
+            gl::BindBuffer(gl::UNIFORM_BUFFER, self.buffer);
+            gl::BufferSubData(gl::UNIFORM_BUFFER, 0, size_of::<T>() as isize, value as *const T as *const c_void);
+        }
+    }
+
+    /// Make a block of a program read this buffer, after checking that `T` has the layout of the
+    /// block.
+    ///
+    /// # Arguments
+    /// * `program` - A program with an active block called `block_name`
+    /// * `block_name` - Name of the block in the shaders, such as "Lighting"
+    pub fn attach(&self, program: &ShaderProgram, block_name: &str) -> Result<(), String> {
+        {
+            let reflection = program.reflection();
+            let block = reflection.block(block_name)
+                .ok_or_else(|| format!("{} has no active uniform block {}", program.name(), block_name))?;
+            check_layout::<T>(block)?;
+        }
+        program.bind_uniform_block(block_name, self.binding);
+        Ok(())
+    }
+
+    pub fn binding(&self) -> u32 {
+        self.binding
+    }
+
+    /// The GL handle of the buffer.
+    pub fn id(&self) -> u32 {
+        self.buffer
+    }
+}
+
+impl<T: UniformBlock> Drop for UniformBuffer<T> {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteBuffers(1, &self.buffer); }
+    }
+}
+
+/// Make sure every active member of a block has a field at the same offset in `T`, that arrays and
+/// matrices have the size their strides give them, and that `T` is large enough for the whole
+/// block.
+pub fn check_layout<T: UniformBlock>(block: &ActiveBlock) -> Result<(), String> {
+    let type_name = std::any::type_name::<T>();
+    let block_prefix = format!("{}.", block.name);
+
+    for member in &block.members {
+        // Blocks with an instance name prefix their members with the name of the block, and
+        // arrays are named after their first element
+        let name = member.name.strip_prefix(&block_prefix).unwrap_or(&member.name);
+        let name = name.strip_suffix("[0]").unwrap_or(name);
+
+        let field = T::FIELDS.iter().find(|field| field.name == name)
+            .ok_or_else(|| format!("The member {} of the uniform block {} is not a field of {}", name, block.name, type_name))?;
+        if field.offset != member.offset {
+            return Err(format!("{} is at byte {} in {}, but at byte {} in the std140 uniform block {}",
+                               name, field.offset, type_name, member.offset, block.name));
+        }
+        if let Some(size) = strided_size(member).filter(|size| *size != field.size) {
+            return Err(format!("{} is {} bytes long in {}, but {} bytes long in the std140 uniform block {}, \
+                                where every element of an array and every column of a matrix is padded to 16 bytes",
+                               name, field.size, type_name, size, block.name));
+        }
+    }
+
+    if size_of::<T>() < block.data_size {
+        return Err(format!("{} is {} bytes long, but the uniform block {} needs {} bytes, it may be missing \
+                            some padding at its end", type_name, size_of::<T>(), block.name, block.data_size));
+    }
+    Ok(())
+}
+
+/// The size of an array or of a matrix in a block, from its strides. None for anything else.
+fn strided_size(member: &BlockMember) -> Option<usize> {
+    if member.array_stride > 0 {
+        return Some(member.array_stride * member.size as usize);
+    }
+    // matCxR has C columns of R rows, and the stride is between columns, or between rows when
+    // the matrix is declared row_major
+    let (columns, rows) = match member.gl_type {
+        gl::FLOAT_MAT2 => (2, 2),
+        gl::FLOAT_MAT2x3 => (2, 3),
+        gl::FLOAT_MAT2x4 => (2, 4),
+        gl::FLOAT_MAT3x2 => (3, 2),
+        gl::FLOAT_MAT3 => (3, 3),
+        gl::FLOAT_MAT3x4 => (3, 4),
+        gl::FLOAT_MAT4x2 => (4, 2),
+        gl::FLOAT_MAT4x3 => (4, 3),
+        gl::FLOAT_MAT4 => (4, 4),
+        _ => return None,
+    };
+    Some(member.matrix_stride * if member.row_major { rows } else { columns })
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use bytemuck::{Pod, Zeroable};
+    use crate::context_config::ContextConfig;
+    use crate::headless::HeadlessContext;
+    use crate::shader::{ShaderSource, ShaderStage};
+    use super::*;
+
+    const LIGHTING_BLOCK: &str = "layout (std140) uniform Lighting {
+    vec3 color;
+    float intensity;
+    vec3 direction;
+    vec4 ambient;
+};
+";
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Pod, Zeroable)]
+    struct Lighting {
+        color: [f32; 3],
+        intensity: f32,
+        direction: [f32; 3],
+        // vec4 is aligned to 16 bytes
+        _padding: f32,
+        ambient_color: [f32; 4],
+    }
+    uniform_block!(Lighting { color, intensity, direction, ambient_color as "ambient" });
+
+    /// Lighting without the padding before `ambient`.
+    #[repr(C)]
+    #[derive(Clone, Copy, Pod, Zeroable)]
+    struct UnpaddedLighting {
+        color: [f32; 3],
+        intensity: f32,
+        direction: [f32; 3],
+        ambient: [f32; 4],
+        _padding: f32,
+    }
+    uniform_block!(UnpaddedLighting { color, intensity, direction, ambient });
+
+    /// A program drawing a fullscreen triangle in a color computed from the block.
+    fn lighting_program(color: &str) -> ShaderProgram {
+        let fragment_shader = format!("#version 330 core\nout vec4 FragColor;\n{}\
+            void main() {{ FragColor = {}; }}\n", LIGHTING_BLOCK, color);
+        ShaderProgram::new(&[
+            ShaderSource::new("fullscreen.vert", "#version 330 core\n\
+                void main() { gl_Position = vec4(gl_VertexID == 1 ? 3.0 : -1.0, gl_VertexID == 2 ? 3.0 : -1.0, 0.0, 1.0); }\n",
+                              ShaderStage::Vertex),
+            ShaderSource::new("lighting.frag", &fragment_shader, ShaderStage::Fragment),
+        ]).unwrap()
+    }
+
+    fn draw_pixel(context: &HeadlessContext, program: &ShaderProgram) -> [u8; 4] {
+        context.framebuffer().bind();
+        program.use_program();
+        unsafe { gl::DrawArrays(gl::TRIANGLES, 0, 3); }
+        context.finish_frame();
+        context.read_pixels().pixel(0, 0)
+    }
+
+    #[test]
+    fn blocks_are_shared_between_programs() {
+        let context = HeadlessContext::new(&ContextConfig::default(), 1, 1).unwrap();
+        let mut vertex_array_object = 0;
+        unsafe {
+            gl::GenVertexArrays(1, &mut vertex_array_object);
+            gl::BindVertexArray(vertex_array_object);
+        }
+
+        let mut lighting = Lighting {
+            color: [1.0, 0.0, 0.0],
+            intensity: 0.5,
+            direction: [0.0, 0.0, 1.0],
+            _padding: 0.0,
+            ambient_color: [0.0, 0.0, 1.0, 1.0],
+        };
+        let lighting_buffer = UniformBuffer::new(&lighting, 3);
+        let colored = lighting_program("vec4(color * intensity + direction * 0.0, 1.0)");
+        let ambient = lighting_program("ambient");
+        lighting_buffer.attach(&colored, "Lighting").unwrap();
+        lighting_buffer.attach(&ambient, "Lighting").unwrap();
+
+        assert_eq!(draw_pixel(&context, &colored), [128, 0, 0, 255]);
+        assert_eq!(draw_pixel(&context, &ambient), [0, 0, 255, 255]);
+
+        lighting.ambient_color = [0.0, 1.0, 0.0, 1.0];
+        lighting_buffer.write(&lighting);
+        assert_eq!(draw_pixel(&context, &ambient), [0, 255, 0, 255]);
+
+        unsafe { gl::DeleteVertexArrays(1, &vertex_array_object); }
+    }
+
+    #[test]
+    fn mismatched_layouts_are_errors() {
+        let _context = HeadlessContext::new(&ContextConfig::default(), 1, 1).unwrap();
+
+        let program = lighting_program("ambient * vec4(color * intensity + direction, 1.0)");
+        let reflection = program.reflection();
+        let block = reflection.block("Lighting").unwrap();
+        let offsets: Vec<(&str, usize)> = block.members.iter().map(|member| (member.name.as_str(), member.offset)).collect();
+        assert_eq!(offsets, [("color", 0), ("intensity", 12), ("direction", 16), ("ambient", 32)]);
+        assert_eq!(block.data_size, 48);
+
+        assert_eq!(check_layout::<Lighting>(block), Ok(()));
+        let error = check_layout::<UnpaddedLighting>(block).unwrap_err();
+        assert!(error.starts_with("ambient is at byte 28 in "), "{}", error);
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Pod, Zeroable)]
+    struct Transform {
+        rotation: [[f32; 4]; 3],
+        weights: [[f32; 4]; 4],
+    }
+    uniform_block!(Transform { rotation, weights });
+
+    /// Transform with the floats of `weights` packed together.
+    #[repr(C)]
+    #[derive(Clone, Copy, Pod, Zeroable)]
+    struct PackedWeightsTransform {
+        rotation: [[f32; 4]; 3],
+        weights: [f32; 4],
+        _padding: [f32; 12],
+    }
+    uniform_block!(PackedWeightsTransform { rotation, weights });
+
+    /// Transform with the columns of `rotation` packed together.
+    #[repr(C)]
+    #[derive(Clone, Copy, Pod, Zeroable)]
+    struct PackedRotationTransform {
+        rotation: [[f32; 3]; 3],
+        _padding: [f32; 3],
+        weights: [[f32; 4]; 4],
+    }
+    uniform_block!(PackedRotationTransform { rotation, weights });
+
+    #[test]
+    fn arrays_and_matrices_are_checked_with_their_strides() {
+        let _context = HeadlessContext::new(&ContextConfig::default(), 1, 1).unwrap();
+
+        let program = ShaderProgram::new(&[
+            ShaderSource::new("position.vert", "#version 330 core\n\
+                void main() { gl_Position = vec4(0.0); }\n", ShaderStage::Vertex),
+            ShaderSource::new("transform.frag", "#version 330 core\nout vec4 FragColor;\n\
+                layout (std140) uniform Transform {\n    mat3 rotation;\n    float weights[4];\n};\n\
+                void main() { FragColor = vec4(rotation * vec3(weights[0] + weights[3]), 1.0); }\n",
+                              ShaderStage::Fragment),
+        ]).unwrap();
+        let reflection = program.reflection();
+        let block = reflection.block("Transform").unwrap();
+
+        assert_eq!(check_layout::<Transform>(block), Ok(()));
+        let error = check_layout::<PackedWeightsTransform>(block).unwrap_err();
+        assert!(error.starts_with("weights is 16 bytes long in "), "{}", error);
+        assert!(error.contains("but 64 bytes long in the std140 uniform block Transform"), "{}", error);
+        let error = check_layout::<PackedRotationTransform>(block).unwrap_err();
+        assert!(error.starts_with("rotation is 36 bytes long in "), "{}", error);
+    }
+
+    /// A row_major mat2x3, which is stored as its 3 rows of 2 floats, each padded to 16 bytes.
+    #[repr(C)]
+    #[derive(Clone, Copy, Pod, Zeroable)]
+    struct Shear {
+        rows: [[f32; 4]; 3],
+        amount: f32,
+        _padding: [f32; 3],
+    }
+    uniform_block!(Shear { rows, amount });
+
+    /// Shear laid out as if the matrix were column major, 2 columns of 3 floats.
+    #[repr(C)]
+    #[derive(Clone, Copy, Pod, Zeroable)]
+    struct ColumnMajorShear {
+        rows: [[f32; 4]; 2],
+        _padding: [f32; 4],
+        amount: f32,
+        _end_padding: [f32; 3],
+    }
+    uniform_block!(ColumnMajorShear { rows, amount });
+
+    #[test]
+    fn row_major_matrices_are_checked_by_rows() {
+        let _context = HeadlessContext::new(&ContextConfig::default(), 1, 1).unwrap();
+
+        let program = ShaderProgram::new(&[
+            ShaderSource::new("position.vert", "#version 330 core\n\
+                void main() { gl_Position = vec4(0.0); }\n", ShaderStage::Vertex),
+            ShaderSource::new("shear.frag", "#version 330 core\nout vec4 FragColor;\n\
+                layout (std140) uniform Shear {\n    layout (row_major) mat2x3 rows;\n    float amount;\n};\n\
+                void main() { FragColor = vec4(rows * vec2(amount), 1.0); }\n",
+                              ShaderStage::Fragment),
+        ]).unwrap();
+        let reflection = program.reflection();
+        let block = reflection.block("Shear").unwrap();
+        assert!(block.members[0].row_major);
+        assert_eq!(block.members[1].offset, 48);
+
+        assert_eq!(check_layout::<Shear>(block), Ok(()));
+        let error = check_layout::<ColumnMajorShear>(block).unwrap_err();
+        assert!(error.starts_with("rows is 32 bytes long in "), "{}", error);
+    }
+}
@@ -0,0 +1,390 @@
+/*
+Vertex buffer objects (VBOs): the buffers the vertices are read from when drawing.
+
+Moving memory from the CPU to the GPU is costly, so the vertices are uploaded all at once when the
+buffer is created, and only the ranges that change are written again afterwards. The usage hint
+tells the driver how often that happens, so it can pick the right kind of memory.
+
+The layout of the vertices is described separately, by the vertex attributes of the vertex array
+object bound while the buffer is (see `gl::VertexAttribPointer`). A `#[repr(C)]` vertex struct can
+describe it itself, with `vertex_layout!`:
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Pod, Zeroable)]
+    struct ColoredVertex {
+        position: [f32; 3],
+        color: Normalized<[u8; 4]>,
+    }
+    vertex_layout!(ColoredVertex { position: 0, color: 1 });
+
+which gives the location of the attribute of each field, and fails to compile when two fields share
+a location. The number of components, their type and whether they are normalized come from the
+type of the field, and the stride and the offsets from the struct, so
+`VertexBuffer::enable_attributes` sets every attribute up in one call. Integer fields that are not
+normalized are read as they are by ivec and uvec inputs, anything else by float inputs.
+ */
+
+use std::ffi::c_void;
+use std::marker::PhantomData;
+use std::mem::{size_of, size_of_val};
+use bytemuck::{Pod, Zeroable};
+
+/// How often the content of a buffer changes, which lets the driver decide where to keep it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BufferUsage {
+    /// Written once and drawn many times.
+    #[default]
+    Static,
+    /// Written again from time to time, and drawn many times in between.
+    Dynamic,
+    /// Written again before almost every draw.
+    Stream,
+}
+
+impl BufferUsage {
+    /// The matching usage for `gl::BufferData`, such as gl::STATIC_DRAW.
+    pub fn gl_usage(self) -> u32 {
+        match self {
+            BufferUsage::Static => gl::STATIC_DRAW,
+            BufferUsage::Dynamic => gl::DYNAMIC_DRAW,
+            BufferUsage::Stream => gl::STREAM_DRAW,
+        }
+    }
+}
+
+/// A type a field of a vertex can have, such as `[f32; 3]` for a vec3 attribute, or `[i32; 2]` for
+/// an ivec2.
+pub trait AttributeValue: Pod {
+    /// Number of components, from 1 to 4.
+    const COMPONENTS: i32;
+    /// Type of the components, such as gl::FLOAT.
+    const GL_TYPE: u32;
+    /// Whether the components are integers the shader reads as they are, see
+    /// `gl::VertexAttribIPointer`.
+    const INTEGER: bool;
+    /// Whether integers are mapped to [0, 1] (or [-1, 1] when signed) and read as floats.
+    const NORMALIZED: bool = false;
+}
+
+macro_rules! attribute_values {
+    ($($component:ty => $gl_type:expr, $integer:expr);*) => {
+        $(
+            impl AttributeValue for $component {
+                const COMPONENTS: i32 = 1;
+                const GL_TYPE: u32 = $gl_type;
+                const INTEGER: bool = $integer;
+            }
+            attribute_values!(@arrays $component => $gl_type, $integer; 1, 2, 3, 4);
+        )*
+    };
+    // Only arrays of up to 4 components, which is all an attribute has
+    (@arrays $component:ty => $gl_type:expr, $integer:expr; $($components:literal),*) => {
+        $(
+            impl AttributeValue for [$component; $components] {
+                const COMPONENTS: i32 = $components;
+                const GL_TYPE: u32 = $gl_type;
+                const INTEGER: bool = $integer;
+            }
+        )*
+    };
+}
+attribute_values!(f32 => gl::FLOAT, false; i8 => gl::BYTE, true; u8 => gl::UNSIGNED_BYTE, true;
+                  i16 => gl::SHORT, true; u16 => gl::UNSIGNED_SHORT, true; i32 => gl::INT, true;
+                  u32 => gl::UNSIGNED_INT, true);
+
+/// Integer components read by the shader as normalized floats, such as `Normalized<[u8; 4]>` for
+/// a color with 8 bits per channel.
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Normalized<T>(pub T);
+
+unsafe impl<T: Zeroable> Zeroable for Normalized<T> {}
+unsafe impl<T: Pod> Pod for Normalized<T> {}
+
+impl<T: AttributeValue> AttributeValue for Normalized<T> {
+    const COMPONENTS: i32 = T::COMPONENTS;
+    const GL_TYPE: u32 = T::GL_TYPE;
+    const INTEGER: bool = false;
+    const NORMALIZED: bool = true;
+}
+
+/// A vertex attribute, read from a field of every vertex.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VertexAttribute {
+    /// The `layout (location = ...)` of the attribute in the vertex shader.
+    pub location: u32,
+    pub components: i32,
+    pub gl_type: u32,
+    /// Whether the shader reads integers (an ivec or uvec input) rather than floats.
+    pub integer: bool,
+    pub normalized: bool,
+    /// Offset of the field in the vertex, in bytes.
+    pub offset: usize,
+}
+
+impl VertexAttribute {
+    /// The attribute of a field of type `T`, usually created by `vertex_layout!`.
+    ///
+    /// # Arguments
+    /// * `location` - The location of the attribute in the vertex shader
+    /// * `offset` - The offset of the field in the vertex
+    /// * `_field` - Returns the field of a vertex, only there to infer `T` from
+    pub const fn of_field<V, T: AttributeValue>(location: u32, offset: usize, _field: fn(&V) -> &T) -> VertexAttribute {
+        VertexAttribute {
+            location,
+            components: T::COMPONENTS,
+            gl_type: T::GL_TYPE,
+            integer: T::INTEGER,
+            normalized: T::NORMALIZED,
+            offset,
+        }
+    }
+}
+
+/// The first location used by two of `attributes`, if any.
+pub const fn duplicate_location(attributes: &[VertexAttribute]) -> Option<u32> {
+    let mut first = 0;
+    while first < attributes.len() {
+        let mut second = first + 1;
+        while second < attributes.len() {
+            if attributes[first].location == attributes[second].location {
+                return Some(attributes[first].location);
+            }
+            second += 1;
+        }
+        first += 1;
+    }
+    None
+}
+
+/// A `#[repr(C)]` struct holding a whole vertex, usually implemented with `vertex_layout!`.
+pub trait Vertex: Pod {
+    /// The attribute of each field.
+    const ATTRIBUTES: &'static [VertexAttribute];
+}
+
+/// Implement `Vertex` for a struct, from the location of the attribute of each field, such as
+/// `vertex_layout!(ColoredVertex { position: 0, color: 1 })`. The fields must implement
+/// `AttributeValue`, and the locations be different.
+#[macro_export]
+macro_rules! vertex_layout {
+    ($vertex:ty { $($field:ident: $location:literal),* $(,)? }) => {
+        impl $crate::vertex_buffer::Vertex for $vertex {
+            const ATTRIBUTES: &'static [$crate::vertex_buffer::VertexAttribute] = &[
+                $($crate::vertex_buffer::VertexAttribute::of_field(
+                    $location,
+                    std::mem::offset_of!($vertex, $field),
+                    |vertex: &$vertex| &vertex.$field,
+                )),*
+            ];
+        }
+        const _: () = assert!(
+            $crate::vertex_buffer::duplicate_location(<$vertex as $crate::vertex_buffer::Vertex>::ATTRIBUTES).is_none(),
+            concat!("Two fields of ", stringify!($vertex), " have the same location"),
+        );
+    };
+}
+
+/// A vertex that is only a position, read by the attribute at location 0.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Pod, Zeroable)]
+pub struct PositionVertex {
+    pub position: [f32; 3],
+}
+vertex_layout!(PositionVertex { position: 0 });
+
+/// Set up and enable the attributes of `T`, reading from the buffer bound to gl::ARRAY_BUFFER, in
+/// the vertex array object currently bound. `VertexBuffer::enable_attributes` binds its own buffer
+/// first, this is for the buffers that are not `VertexBuffer`s.
+pub fn enable_vertex_attributes<T: Vertex>() {
+    for attribute in T::ATTRIBUTES {
+        let offset = attribute.offset as *const c_void;
+        unsafe {
+            // VertexAttribPointer would convert integers to floats
+            if attribute.integer {
+                gl::VertexAttribIPointer(attribute.location, attribute.components, attribute.gl_type,
+                                         size_of::<T>() as i32, offset);
+            } else {
+                gl::VertexAttribPointer(attribute.location, attribute.components, attribute.gl_type,
+                                        if attribute.normalized { gl::TRUE } else { gl::FALSE },
+                                        size_of::<T>() as i32, offset);
+            }
+            gl::EnableVertexAttribArray(attribute.location);
+        }
+    }
+}
+
+/// A vertex buffer holding an array of `T`, deleted when dropped.
+///
+/// Like every GL object it must be dropped while its context is still current.
+#[derive(Debug)]
+pub struct VertexBuffer<T: Pod> {
+    buffer: u32,
+    len: usize,
+    usage: BufferUsage,
+    vertex_type: PhantomData<T>,
+}
+
+impl<T: Pod> VertexBuffer<T> {
+    /// Create a buffer holding a copy of `vertices`. The buffer is left bound to gl::ARRAY_BUFFER,
+    /// so the vertex attributes set up next read from it.
+    ///
+    /// # Arguments
+    /// * `vertices` - The initial content, its length is the length of the buffer
+    /// * `usage` - How often the content will change
+    pub fn new(vertices: &[T], usage: BufferUsage) -> VertexBuffer<T> {
+        let mut buffer = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut buffer);
+            gl::BindBuffer(gl::ARRAY_BUFFER, buffer);
+            gl::BufferData(gl::ARRAY_BUFFER, size_of_val(vertices) as isize,
+                           vertices.as_ptr() as *const c_void, usage.gl_usage());
+        }
+
+        VertexBuffer {
+            buffer,
+            len: vertices.len(),
+            usage,
+            vertex_type: PhantomData,
+        }
+    }
+
+    /// Bind the buffer to gl::ARRAY_BUFFER.
+    pub fn bind(&self) {
+        unsafe { gl::BindBuffer(gl::ARRAY_BUFFER, self.buffer); }
+    }
+
+    /// Replace some of the vertices of the buffer, the others are left as they are.
+    ///
+    /// # Arguments
+    /// * `first` - Index of the first vertex to replace
+    /// * `vertices` - The new vertices, which must fit in the buffer from `first` on
+    pub fn write(&self, first: usize, vertices: &[T]) {
+        assert!(first + vertices.len() <= self.len,
+                "Can't write {} vertices from {} into a vertex buffer of {}", vertices.len(), first, self.len);
+        self.bind();
+        unsafe {
+            gl::BufferSubData(gl::ARRAY_BUFFER, (first * size_of::<T>()) as isize, size_of_val(vertices) as isize,
+                              vertices.as_ptr() as *const c_void);
+        }
+    }
+
+    /// The GL handle of the buffer, for the calls this type doesn't wrap.
+    pub fn id(&self) -> u32 {
+        self.buffer
+    }
+
+    /// Number of vertices in the buffer.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn usage(&self) -> BufferUsage {
+        self.usage
+    }
+}
+
+impl<T: Vertex> VertexBuffer<T> {
+    /// Set up and enable the attributes of `T`, reading from this buffer, in the vertex array
+    /// object currently bound.
+    pub fn enable_attributes(&self) {
+        self.bind();
+        enable_vertex_attributes::<T>();
+    }
+}
+
+impl<T: Pod> Drop for VertexBuffer<T> {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteBuffers(1, &self.buffer); }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use bytemuck::{Pod, Zeroable};
+    use crate::context_config::ContextConfig;
+    use crate::headless::HeadlessContext;
+    use super::*;
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Pod, Zeroable)]
+    struct ColoredVertex {
+        position: [f32; 3],
+        color: Normalized<[u8; 4]>,
+        size: f32,
+        material: [u16; 2],
+    }
+    vertex_layout!(ColoredVertex { position: 0, color: 2, size: 1, material: 3 });
+
+    fn read_back(vertex_buffer: &VertexBuffer<[f32; 2]>) -> Vec<[f32; 2]> {
+        let mut vertices = vec![[0.0; 2]; vertex_buffer.len()];
+        vertex_buffer.bind();
+        unsafe {
+            gl::GetBufferSubData(gl::ARRAY_BUFFER, 0, size_of_val(vertices.as_slice()) as isize,
+                                 vertices.as_mut_ptr() as *mut c_void);
+        }
+        vertices
+    }
+
+    #[test]
+    fn vertices_are_written_in_place_and_deleted_on_drop() {
+        let _context = HeadlessContext::new(&ContextConfig::default(), 1, 1).unwrap();
+
+        let vertex_buffer = VertexBuffer::new(&[[0.0, 1.0], [2.0, 3.0], [4.0, 5.0]], BufferUsage::Dynamic);
+        assert_eq!((vertex_buffer.len(), vertex_buffer.usage()), (3, BufferUsage::Dynamic));
+        let mut usage = 0;
+        unsafe { gl::GetBufferParameteriv(gl::ARRAY_BUFFER, gl::BUFFER_USAGE, &mut usage); }
+        assert_eq!(usage as u32, gl::DYNAMIC_DRAW);
+
+        vertex_buffer.write(1, &[[6.0, 7.0]]);
+        assert_eq!(read_back(&vertex_buffer), [[0.0, 1.0], [6.0, 7.0], [4.0, 5.0]]);
+
+        let buffer = vertex_buffer.id();
+        drop(vertex_buffer);
+        assert_eq!(unsafe { gl::IsBuffer(buffer) }, gl::FALSE);
+    }
+
+    #[test]
+    fn attributes_come_from_the_vertex_struct() {
+        assert_eq!(ColoredVertex::ATTRIBUTES, [
+            VertexAttribute { location: 0, components: 3, gl_type: gl::FLOAT, integer: false, normalized: false, offset: 0 },
+            VertexAttribute { location: 2, components: 4, gl_type: gl::UNSIGNED_BYTE, integer: false, normalized: true, offset: 12 },
+            VertexAttribute { location: 1, components: 1, gl_type: gl::FLOAT, integer: false, normalized: false, offset: 16 },
+            VertexAttribute { location: 3, components: 2, gl_type: gl::UNSIGNED_SHORT, integer: true, normalized: false, offset: 20 },
+        ]);
+        assert_eq!(duplicate_location(ColoredVertex::ATTRIBUTES), None);
+        let mut attributes = ColoredVertex::ATTRIBUTES.to_vec();
+        attributes[3].location = 1;
+        assert_eq!(duplicate_location(&attributes), Some(1));
+
+        let _context = HeadlessContext::new(&ContextConfig::default(), 1, 1).unwrap();
+        let mut vertex_array_object = 0;
+        unsafe {
+            gl::GenVertexArrays(1, &mut vertex_array_object);
+            gl::BindVertexArray(vertex_array_object);
+        }
+        let vertex_buffer = VertexBuffer::new(&[ColoredVertex::zeroed(); 3], BufferUsage::Static);
+        vertex_buffer.enable_attributes();
+
+        let attribute = |location, parameter| {
+            let mut value = 0;
+            unsafe { gl::GetVertexAttribiv(location, parameter, &mut value); }
+            value
+        };
+        assert_eq!(attribute(2, gl::VERTEX_ATTRIB_ARRAY_ENABLED), 1);
+        assert_eq!(attribute(2, gl::VERTEX_ATTRIB_ARRAY_STRIDE), 24);
+        assert_eq!(attribute(2, gl::VERTEX_ATTRIB_ARRAY_NORMALIZED), 1);
+        assert_eq!(attribute(2, gl::VERTEX_ATTRIB_ARRAY_INTEGER), 0);
+        assert_eq!(attribute(3, gl::VERTEX_ATTRIB_ARRAY_INTEGER), 1);
+        assert_eq!(attribute(2, gl::VERTEX_ATTRIB_ARRAY_BUFFER_BINDING) as u32, vertex_buffer.id());
+        let mut offset = std::ptr::null_mut();
+        unsafe { gl::GetVertexAttribPointerv(1, gl::VERTEX_ATTRIB_ARRAY_POINTER, std::ptr::addr_of_mut!(offset)); }
+        assert_eq!(offset as usize, 16);
+
+        unsafe { gl::DeleteVertexArrays(1, &vertex_array_object); }
+    }
+}
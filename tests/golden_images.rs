@@ -0,0 +1,93 @@
+/*
+Golden image regression tests.
+
+Every registered exercise is rendered headlessly with Mesa's llvmpipe software rasterizer, at a
+fixed size and a fixed point in time, and compared to its reference image in tests/golden.
+
+When an exercise changes on purpose, regenerate its reference with:
+    UPDATE_GOLDEN_IMAGES=1 cargo test --test golden_images
+and check the new PNGs in together with the change.
+ */
+
+#![cfg(target_os = "linux")]
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use opengl::context_config::ContextConfig;
+use opengl::exercises::EXERCISES;
+use opengl::headless::HeadlessContext;
+use opengl::image::Image;
+
+const WIDTH: u32 = 160;
+const HEIGHT: u32 = 120;
+
+/// Render every exercise half a second into its animation
+const FRAME: u64 = 30;
+const FRAME_RATE: u32 = 60;
+
+/// Largest difference allowed in a single color channel, to absorb rounding differences between
+/// Mesa versions
+const TOLERANCE: u8 = 2;
+
+fn golden_image_path(exercise_id: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.png", exercise_id.replace('/', "-")))
+}
+
+fn failure_output_path(exercise_id: &str, suffix: &str) -> PathBuf {
+    Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join("golden-images")
+        .join(format!("{}-{}.png", exercise_id.replace('/', "-"), suffix))
+}
+
+#[test]
+fn exercises_match_golden_images() {
+    // The references are rendered by llvmpipe, so the tests must be too, even on machines that
+    // have a GPU
+    env::set_var("LIBGL_ALWAYS_SOFTWARE", "1");
+    let update_golden_images = env::var_os("UPDATE_GOLDEN_IMAGES").is_some();
+
+    let headless_context = HeadlessContext::new(&ContextConfig::default(), WIDTH, HEIGHT).unwrap();
+    let mut failures = Vec::new();
+
+    for exercise in EXERCISES {
+        let id = exercise.id();
+        let actual = headless_context.render_frame((exercise.create)().as_mut(), FRAME, FRAME_RATE)
+            .unwrap_or_else(|error| panic!("Could not set up {}: {}", id, error));
+        let golden_path = golden_image_path(&id);
+
+        if update_golden_images {
+            actual.save_png(&golden_path).unwrap();
+            continue;
+        }
+
+        let expected = match Image::load_png(&golden_path) {
+            Ok(expected) => expected,
+            Err(message) => {
+                let actual_path = failure_output_path(&id, "actual");
+                actual.save_png(&actual_path).unwrap();
+                failures.push(format!("{}: {}, the rendered image was saved to {}",
+                                      id, message, actual_path.display()));
+                continue;
+            }
+        };
+
+        match actual.compare(&expected, TOLERANCE) {
+            Ok(comparison) if comparison.matches() => {}
+            Ok(comparison) => {
+                let actual_path = failure_output_path(&id, "actual");
+                let diff_path = failure_output_path(&id, "diff");
+                actual.save_png(&actual_path).unwrap();
+                comparison.diff.save_png(&diff_path).unwrap();
+                failures.push(format!("{}: {} pixels differ by up to {}, see {} and {}",
+                                      id, comparison.mismatched_pixels, comparison.max_difference,
+                                      actual_path.display(), diff_path.display()));
+            }
+            Err(message) => failures.push(format!("{}: {}", id, message)),
+        }
+    }
+
+    assert!(failures.is_empty(), "Exercises don't match their golden images:\n{}", failures.join("\n"));
+}